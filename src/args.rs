@@ -1,15 +1,27 @@
-use std::path::PathBuf;
+use std::{
+    io::Read,
+    ops::{Bound, RangeBounds},
+    path::PathBuf,
+    str::FromStr,
+};
 
-use clap::{Parser, Subcommand};
+use anyhow::{bail, Context, Result};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 
 use clap_complete::Shell;
-use pngwhisper::png::chunk_type::ChunkType;
+use pngwhisper::png::{
+    chunk::Chunk, chunk_type::ChunkType, compression::Compression, lsb::Channels, payload::Payload,
+    ChunkPosition,
+};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None, arg_required_else_help = true)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    /// Accept files that are truncated, missing 'IEND' or have bad CRCs, keeping any trailing data as is
+    #[arg(long, global = true)]
+    pub lenient: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -19,22 +31,98 @@ pub enum Commands {
     Encode {
         #[arg()]
         file: PathBuf,
-        #[arg()]
-        message: String,
+        #[command(flatten)]
+        input: InputArgs,
+        /// Where to hide the message
+        #[arg(long, value_enum, default_value_t = Method::Chunk)]
+        method: Method,
+        /// Channels whose least significant bits carry the message with '--method lsb'
+        #[arg(long, default_value = "rgb")]
+        channels: Option<Channels>,
+        /// Scatter the message across the image with a key, read like the passphrase, with '--method lsb'
+        #[arg(short, long)]
+        scatter: bool,
         /// Chunk type for the message
         #[arg(short, long, default_value = "wsPr")]
         kind: Option<ChunkType>,
+        /// Where to insert the message chunk: 'before-iend', 'after-ihdr', 'before-idat' or a chunk index
+        #[arg(short, long, default_value = "before-iend")]
+        position: Option<ChunkPosition>,
+        /// Compress the message before embedding it: 'none' or 'deflate'
+        #[arg(short, long, default_value = "none")]
+        compress: Option<Compression>,
+        /// Encrypt the message with a passphrase
+        #[arg(short, long)]
+        encrypt: bool,
+        /// Split the message across multiple chunks holding at most this many bytes each
+        #[arg(short, long, value_name = "BYTES", default_value_t = Chunk::MAX_LENGTH)]
+        max_chunk_size: usize,
+        /// Replace the chunks of the same type instead of adding to them, keeping their place
+        #[arg(long)]
+        replace: bool,
+        #[command(flatten)]
+        passphrase: PassphraseArgs,
+        #[command(flatten)]
+        write: WriteArgs,
+    },
+    /// Edit a hidden text message in $VISUAL or $EDITOR, keeping its place in the image
+    #[command()]
+    Edit {
+        #[arg()]
+        file: PathBuf,
+        /// Chunk type used for the encoded message
+        #[arg(short, long, default_value = "wsPr")]
+        kind: Option<ChunkType>,
+        /// Compress the edited message before embedding it: 'none' or 'deflate', as before by default
+        #[arg(short, long)]
+        compress: Option<Compression>,
+        /// Encrypt a new message with a passphrase, encrypted messages always stay encrypted
+        #[arg(short, long)]
+        encrypt: bool,
+        /// Split the edited message across chunks of at most this many bytes, as before by default
+        #[arg(short, long, value_name = "BYTES")]
+        max_chunk_size: Option<usize>,
+        #[command(flatten)]
+        passphrase: PassphraseArgs,
+        #[command(flatten)]
+        write: WriteArgs,
     },
     /// Decode a message in a PNG image
     #[command()]
     Decode {
         #[arg()]
         file: PathBuf,
+        /// Where the message is hidden
+        #[arg(long, value_enum, default_value_t = Method::Chunk)]
+        method: Method,
+        /// Channels whose least significant bits carry the message with '--method lsb'
+        #[arg(long, default_value = "rgb")]
+        channels: Option<Channels>,
+        /// Scatter the message across the image with a key, read like the passphrase, with '--method lsb'
+        #[arg(short, long)]
+        scatter: bool,
         /// Chunk type used for the encoded message
         #[arg(short, long, default_value = "wsPr")]
         kind: Option<ChunkType>,
+        /// Decrypt the message with a passphrase
+        #[arg(short, long, visible_alias = "encrypt")]
+        decrypt: bool,
+        #[command(flatten)]
+        passphrase: PassphraseArgs,
+        /// Write the exact bytes of the message to a file, or into a directory using the original file name
+        #[arg(short, long, value_name = "PATH", conflicts_with = "raw")]
+        output: Option<PathBuf>,
+        /// Write the exact bytes of the message to stdout
+        #[arg(short, long)]
+        raw: bool,
+        /// Maximum size in bytes a compressed message may expand to
+        #[arg(long, value_name = "BYTES", default_value_t = Compression::DEFAULT_LIMIT)]
+        size_limit: usize,
+        /// How to print the messages
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text, conflicts_with_all = ["output", "raw"])]
+        format: OutputFormat,
     },
-    /// Remove a chunk from a PNG image
+    /// Remove chunks from a PNG image
     #[command(
         after_help = "Caution: Use with care! The PNG file may become corrupted if an incorrect chunk type is used."
     )]
@@ -44,14 +132,85 @@ pub enum Commands {
         /// Chunk type used for the encoded message
         #[arg(short, long, default_value = "wsPr")]
         kind: Option<ChunkType>,
+        /// Remove every chunk of the type instead of only the first one
         #[arg(short, long)]
         all: bool,
+        /// Remove the chunks at these indices, counting from 0 like 'print' and '--position'
+        #[arg(short, long, value_delimiter = ',', conflicts_with_all = ["kind", "all", "range"])]
+        index: Vec<usize>,
+        /// Remove the chunks in a range of indices: 'START..END', 'START..=END' or 'START..'
+        #[arg(short, long, conflicts_with_all = ["kind", "all"])]
+        range: Option<IndexRange>,
+        /// Allow removing critical chunks, which breaks the image
+        #[arg(short, long)]
+        force: bool,
+        #[command(flatten)]
+        write: WriteArgs,
+    },
+    /// Strip the ancillary chunks that aren't needed to display a PNG image
+    #[command()]
+    Strip {
+        #[arg()]
+        file: PathBuf,
+        /// Ancillary chunk types to keep, pass '--keep' alone to keep none of them
+        #[arg(
+            long,
+            value_name = "TYPE",
+            value_delimiter = ',',
+            num_args = 0..,
+            default_value = "gAMA,cHRM,sRGB,iCCP,sBIT,tRNS"
+        )]
+        keep: Vec<ChunkType>,
+        /// Only strip metadata that may tell who made the image, with what, where or when: safe-to-copy chunks like 'eXIf' and text, 'tIME' and private chunks
+        #[arg(long)]
+        privacy: bool,
+        #[command(flatten)]
+        filter: ChunkFilter,
+        #[command(flatten)]
+        write: WriteArgs,
     },
-    /// Prints all of the chunks in a PNG file
+    /// Prints the image header and all of the chunks in a PNG file
     #[command()]
     Print {
         #[arg()]
         file: PathBuf,
+        /// How to print the chunks
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+        /// How to show the data of each chunk with '--format text'
+        #[arg(long, value_enum, default_value_t = DataView::Auto)]
+        view: DataView,
+        /// Show at most this many bytes of each chunk with '--format text', 0 to show all of them
+        #[arg(long, value_name = "BYTES", default_value_t = 256)]
+        max_bytes: usize,
+        #[command(flatten)]
+        filter: ChunkFilter,
+    },
+    /// Report how many bytes can be hidden in a PNG image with each method
+    #[command()]
+    Capacity {
+        #[arg()]
+        file: PathBuf,
+    },
+    /// Check a PNG file against the chunk layout rules of the PNG spec, failing on errors
+    #[command()]
+    Lint {
+        #[arg()]
+        file: PathBuf,
+    },
+    /// Recover a damaged PNG file, skipping garbage between chunks and fixing bad CRCs
+    #[command()]
+    Repair {
+        #[arg()]
+        file: PathBuf,
+        #[command(flatten)]
+        write: WriteArgs,
+    },
+    /// Read and write text metadata stored in 'tEXt', 'zTXt' and 'iTXt' chunks
+    #[command()]
+    Text {
+        #[command(subcommand)]
+        command: TextCommands,
     },
     #[command()]
     /// Generate tab-completion scripts for your shell
@@ -60,3 +219,300 @@ pub enum Commands {
         shell: Shell,
     },
 }
+
+#[derive(Debug, Subcommand)]
+pub enum TextCommands {
+    /// List all of the text chunks in a PNG image
+    #[command()]
+    List {
+        #[arg()]
+        file: PathBuf,
+    },
+    /// Print the text stored under a keyword
+    #[command()]
+    Get {
+        #[arg()]
+        file: PathBuf,
+        #[arg()]
+        keyword: String,
+    },
+    /// Store text under a keyword, replacing any text chunks with the same keyword
+    #[command()]
+    Set {
+        #[arg()]
+        file: PathBuf,
+        #[arg()]
+        keyword: String,
+        #[arg()]
+        text: String,
+        /// Compress the text
+        #[arg(short = 'z', long)]
+        compress: bool,
+        /// Store the text as UTF-8 in an 'iTXt' chunk, implied for text outside of Latin-1
+        #[arg(short, long)]
+        international: bool,
+        /// Language of the text, such as 'en-US'
+        #[arg(short, long, value_name = "TAG")]
+        language: Option<String>,
+        /// The keyword translated into the language of the text
+        #[arg(short, long, value_name = "KEYWORD")]
+        translated_keyword: Option<String>,
+        /// Where to insert a new text chunk: 'before-iend', 'after-ihdr', 'before-idat' or a chunk index
+        #[arg(short, long, default_value = "before-iend")]
+        position: Option<ChunkPosition>,
+        #[command(flatten)]
+        write: WriteArgs,
+    },
+    /// Remove all of the text chunks with a keyword
+    #[command()]
+    Remove {
+        #[arg()]
+        file: PathBuf,
+        #[arg()]
+        keyword: String,
+        #[command(flatten)]
+        write: WriteArgs,
+    },
+}
+
+/// Where a message is hidden in the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    /// In ancillary chunks of its own
+    Chunk,
+    /// In the least significant bits of the pixel samples
+    Lsb,
+}
+
+/// A range of chunk indices, end exclusive unless written with '..='.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexRange {
+    pub start: usize,
+    pub end: Bound<usize>,
+}
+
+impl FromStr for IndexRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |index: &str| {
+            index
+                .trim()
+                .parse::<usize>()
+                .with_context(|| format!("Invalid index '{}' in range '{}'", index, s))
+        };
+        let (start, end) = s
+            .split_once("..")
+            .with_context(|| format!("Invalid range '{}', expected 'START..END'", s))?;
+        let end = match end.strip_prefix('=') {
+            Some(end) => Bound::Included(parse(end)?),
+            None if end.trim().is_empty() => Bound::Unbounded,
+            None => Bound::Excluded(parse(end)?),
+        };
+        let start = parse(start)?;
+        if let Bound::Included(end) | Bound::Excluded(end) = end {
+            if start > end {
+                bail!(
+                    "Invalid range '{}', the start {} is after the end {}",
+                    s,
+                    start,
+                    end
+                )
+            }
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl RangeBounds<usize> for IndexRange {
+    fn start_bound(&self) -> Bound<&usize> {
+        Bound::Included(&self.start)
+    }
+
+    fn end_bound(&self) -> Bound<&usize> {
+        self.end.as_ref()
+    }
+}
+
+/// How to print the results of a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Coloured text for humans
+    Text,
+    /// A single JSON document
+    Json,
+    /// One JSON object per line, printed as soon as it is read
+    Ndjson,
+}
+
+/// How to show the data of a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DataView {
+    /// Text for chunks holding text, a hex dump for binary ones
+    Auto,
+    /// Text, replacing invalid UTF-8
+    Text,
+    /// A hex dump with offsets
+    Hex,
+}
+
+/// A property of a chunk type, encoded in the case of its letters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChunkProperty {
+    Critical,
+    Ancillary,
+    Public,
+    Private,
+    SafeToCopy,
+    UnsafeToCopy,
+}
+
+impl ChunkProperty {
+    pub fn matches(&self, chunk_type: &ChunkType) -> bool {
+        match self {
+            ChunkProperty::Critical => chunk_type.is_critical(),
+            ChunkProperty::Ancillary => !chunk_type.is_critical(),
+            ChunkProperty::Public => chunk_type.is_public(),
+            ChunkProperty::Private => !chunk_type.is_public(),
+            ChunkProperty::SafeToCopy => chunk_type.is_safe_to_copy(),
+            ChunkProperty::UnsafeToCopy => !chunk_type.is_safe_to_copy(),
+        }
+    }
+}
+
+/// Which chunks to select. Every chunk is selected if no filters are given.
+#[derive(Debug, Args)]
+pub struct ChunkFilter {
+    /// Only select chunks of these types
+    #[arg(short = 't', long = "type", value_name = "TYPE", value_delimiter = ',')]
+    pub types: Vec<ChunkType>,
+    /// Only select chunks with all of these properties
+    #[arg(
+        long = "only",
+        value_name = "PROPERTY",
+        value_enum,
+        value_delimiter = ','
+    )]
+    pub properties: Vec<ChunkProperty>,
+}
+
+impl ChunkFilter {
+    pub fn matches(&self, chunk_type: &ChunkType) -> bool {
+        (self.types.is_empty() || self.types.contains(chunk_type))
+            && self
+                .properties
+                .iter()
+                .all(|property| property.matches(chunk_type))
+    }
+}
+
+/// Where to write a modified image. The input file is replaced unless an output is given.
+#[derive(Debug, Args)]
+pub struct WriteArgs {
+    /// Write the image to this path instead of overwriting the input file, '-' for stdout
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+    /// Keep the file being replaced with this suffix appended to its name, '.bak' by default
+    #[arg(long, value_name = "SUFFIX", num_args = 0..=1, default_missing_value = ".bak")]
+    pub backup: Option<String>,
+    /// Print which chunks would change instead of writing anything
+    #[arg(long, conflicts_with_all = ["output", "backup"])]
+    pub dry_run: bool,
+}
+
+/// What to embed into the image. Exactly one of these must be given.
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("input").required(true)))]
+pub struct InputArgs {
+    #[arg(group = "input")]
+    pub message: Option<String>,
+    /// Embed the contents of a file, keeping its name
+    #[arg(short = 'f', long = "file", value_name = "PATH", group = "input")]
+    pub input_file: Option<PathBuf>,
+    /// Embed bytes read from stdin
+    #[arg(long, group = "input")]
+    pub stdin: bool,
+}
+
+impl InputArgs {
+    /// Reads the selected input into a `Payload`.
+    pub fn read(self) -> Result<Payload> {
+        if let Some(path) = self.input_file {
+            Ok(Payload::File {
+                name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned()),
+                data: fs_err::read(&path)?,
+            })
+        } else if self.stdin {
+            let mut data = vec![];
+            std::io::stdin().read_to_end(&mut data)?;
+            Ok(Payload::File { name: None, data })
+        } else {
+            Ok(Payload::Text(self.message.unwrap_or_default()))
+        }
+    }
+}
+
+/// Where to take the passphrase from. Prompts on the terminal when neither option is given.
+#[derive(Debug, Args)]
+pub struct PassphraseArgs {
+    /// Read the passphrase from the first line of a file
+    #[arg(long, value_name = "FILE", conflicts_with = "passphrase_env")]
+    pub passphrase_file: Option<PathBuf>,
+    /// Read the passphrase from an environment variable
+    #[arg(long, value_name = "VAR")]
+    pub passphrase_env: Option<String>,
+}
+
+impl PassphraseArgs {
+    /// Reads the passphrase from the selected source, asking for it twice when
+    /// prompting and `confirm` is set.
+    pub fn read(&self, confirm: bool) -> Result<String> {
+        let passphrase = if let Some(path) = &self.passphrase_file {
+            fs_err::read_to_string(path)?
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_owned()
+        } else if let Some(var) = &self.passphrase_env {
+            std::env::var(var)
+                .with_context(|| format!("Environment variable '{}' is not set", var))?
+        } else {
+            let passphrase = rpassword::prompt_password("Passphrase: ")?;
+            if confirm && passphrase != rpassword::prompt_password("Confirm passphrase: ")? {
+                bail!("Passphrases do not match")
+            }
+            passphrase
+        };
+
+        if passphrase.is_empty() {
+            bail!("Passphrase must not be empty")
+        }
+        Ok(passphrase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_range() {
+        let range = IndexRange::from_str("1..3").unwrap();
+        assert_eq!((range.start, range.end), (1, Bound::Excluded(3)));
+        let range = IndexRange::from_str("2..=2").unwrap();
+        assert_eq!((range.start, range.end), (2, Bound::Included(2)));
+        let range = IndexRange::from_str("4..").unwrap();
+        assert_eq!((range.start, range.end), (4, Bound::Unbounded));
+        assert!(IndexRange::from_str("3").is_err());
+        assert!(IndexRange::from_str("a..2").is_err());
+
+        let err = IndexRange::from_str("3..1").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid range '3..1', the start 3 is after the end 1"
+        );
+        assert!(IndexRange::from_str("3..=2").is_err());
+    }
+}
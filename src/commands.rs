@@ -1,58 +1,1035 @@
-use std::path::Path;
+use std::{
+    env, fs,
+    io::{self, BufWriter, Write},
+    ops::Bound,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use console::style;
 use itertools::Itertools;
 
-use pngwhisper::png::{chunk::Chunk, chunk_type::ChunkType, Png};
+use crate::{
+    args::{ChunkFilter, DataView, IndexRange, OutputFormat, WriteArgs},
+    output::{self, ChunkRecord, ImageRecord, PrintDocument},
+};
+use pngwhisper::{
+    error::PngError,
+    png::{
+        chunk::Chunk,
+        chunk_type::ChunkType,
+        compression::Compression,
+        diff::ChunkChange,
+        envelope::Envelope,
+        fragment::Fragment,
+        ihdr::Ihdr,
+        lsb::{self, Channels},
+        payload::Payload,
+        text::TextChunk,
+        validate::Severity,
+        ChunkPosition, ParseMode, Png,
+    },
+};
 
-/// Encodes a message into a PNG image.
-pub fn encode<P>(file: P, message: &str, chunk_type: ChunkType) -> Result<Png>
+/// Seals a payload into chunks of `chunk_type`, compressing it with `compression` and
+/// encrypting it if a `passphrase` is given. The payload is split across several chunks
+/// if it doesn't fit into `max_chunk_size` bytes.
+pub fn message_chunks(
+    payload: &Payload,
+    chunk_type: ChunkType,
+    compression: Compression,
+    passphrase: Option<&str>,
+    max_chunk_size: usize,
+) -> Result<Vec<Chunk>> {
+    if max_chunk_size > Chunk::MAX_LENGTH {
+        bail!(
+            "Chunks can hold at most {} bytes of data",
+            Chunk::MAX_LENGTH
+        )
+    }
+
+    let envelope = Envelope::seal(payload, compression, passphrase.map(str::as_bytes))?;
+    Ok(envelope
+        .split(max_chunk_size)?
+        .into_iter()
+        .map(|data| Chunk::new(chunk_type, data))
+        .collect())
+}
+
+/// Encodes the `chunks` of a message into a PNG image as consecutive chunks at `position`.
+/// With `replace`, they take the place of any chunks of the same type instead, as
+/// `Png::upsert` does, and only go to `position` if there are none.
+pub fn encode(
+    png: &mut Png,
+    chunks: Vec<Chunk>,
+    position: ChunkPosition,
+    replace: bool,
+) -> Result<()> {
+    let mut chunks = chunks.into_iter();
+    let Some(first) = chunks.next() else {
+        return Ok(());
+    };
+    let idx = match replace {
+        true => png.upsert(first, position)?,
+        false => {
+            let idx = png.resolve_position(position)?;
+            png.insert_chunk(first, ChunkPosition::Index(idx))?;
+            idx
+        }
+    };
+    for (i, chunk) in chunks.enumerate() {
+        png.insert_chunk(chunk, ChunkPosition::Index(idx + 1 + i))?;
+    }
+    Ok(())
+}
+
+/// A message hidden in chunks, as found by `find_message`.
+pub struct StoredMessage {
+    pub envelope: Envelope,
+    /// The size of the chunks the message was split across, `Chunk::MAX_LENGTH` if
+    /// it wasn't split.
+    pub max_chunk_size: usize,
+}
+
+impl StoredMessage {
+    pub fn compression(&self) -> Compression {
+        match self.envelope.is_compressed() {
+            true => Compression::Deflate,
+            false => Compression::None,
+        }
+    }
+}
+
+/// Finds the message hidden in the chunks of `chunk_type`, along with how it was stored
+/// so that it can be written back the same way. Fails if there is more than one.
+pub fn find_message(png: &Png, chunk_type: ChunkType) -> Result<Option<StoredMessage>> {
+    let chunks = png.chunks_by_type(&chunk_type);
+    let mut envelopes = Envelope::extract(chunks.iter().map(|chunk| chunk.data()))?;
+    if envelopes.len() > 1 {
+        bail!(
+            "Found {} messages in chunks of type '{}', only a single message can be edited",
+            envelopes.len(),
+            chunk_type
+        )
+    }
+    // All fragments but the last are as large as they could be
+    let max_chunk_size = chunks
+        .iter()
+        .filter(|chunk| Fragment::is_fragment(chunk.data()))
+        .map(|chunk| chunk.length() as usize)
+        .max()
+        .unwrap_or(Chunk::MAX_LENGTH);
+    Ok(envelopes.pop().map(|envelope| StoredMessage {
+        envelope,
+        max_chunk_size,
+    }))
+}
+
+/// Hides a payload in the least significant bits of the selected `channels` of a
+/// PNG image, compressing it with `compression` and encrypting it if a `passphrase` is given.
+/// The payload is scattered across the image if a `scatter_key` is given.
+pub fn encode_lsb(
+    png: &mut Png,
+    payload: &Payload,
+    channels: Channels,
+    compression: Compression,
+    passphrase: Option<&str>,
+    scatter_key: Option<&str>,
+) -> Result<()> {
+    let envelope = Envelope::seal(payload, compression, passphrase.map(str::as_bytes))?;
+    match lsb::embed(png, &envelope, channels, scatter_key.map(str::as_bytes)) {
+        Err(err @ PngError::InsufficientCapacity { .. }) => Err(err).with_context(|| {
+            format!(
+                "The message doesn't fit into the '{}' channels, try compressing it or using more channels",
+                channels
+            )
+        }),
+        result => Ok(result?),
+    }
+}
+
+/// Searches for hidden payloads in a PNG image, decrypting them if a `passphrase` is given.
+/// Payloads split across multiple chunks are reassembled, chunks of the given type that
+/// don't hold pngwhisper data are skipped, and compressed payloads may not expand past
+/// `size_limit` bytes.
+pub fn decode<P>(
+    file: P,
+    chunk_type: ChunkType,
+    passphrase: Option<&str>,
+    size_limit: usize,
+    mode: ParseMode,
+) -> Result<Vec<Payload>>
 where
     P: AsRef<Path>,
 {
-    let mut png = Png::from_file(file)?;
-    png.append_chunk(Chunk::new(chunk_type, message.as_bytes().into()));
-    Ok(png)
+    let chunks: Vec<Chunk> = Png::open_chunks(file, mode)?
+        .filter_ok(|chunk| chunk.chunk_type() == &chunk_type)
+        .try_collect()?;
+    Envelope::extract(chunks.iter().map(Chunk::data))?
+        .iter()
+        .enumerate()
+        .map(|(i, envelope)| {
+            envelope
+                .open_with_limit(passphrase.map(str::as_bytes), size_limit)
+                .with_context(|| format!("Failed to read message {}", i + 1))
+        })
+        .try_collect()
 }
 
-/// Searches for hidden messages in a PNG image.
-pub fn decode<P>(file: P, chunk_type: ChunkType) -> Result<Vec<String>>
+/// Reads a payload hidden in the least significant bits of the selected `channels`
+/// of a PNG image, decrypting it if a `passphrase` is given and locating it with the
+/// `scatter_key` it was scattered with. Returns no payloads if the pixels don't hold
+/// pngwhisper data.
+pub fn decode_lsb<P>(
+    file: P,
+    channels: Channels,
+    passphrase: Option<&str>,
+    scatter_key: Option<&str>,
+    size_limit: usize,
+    mode: ParseMode,
+) -> Result<Vec<Payload>>
 where
     P: AsRef<Path>,
 {
-    let png = Png::from_file(file)?;
-    Ok(png
-        .chunks_by_type(&chunk_type)
-        .into_iter()
-        .map(|chunk| chunk.data_as_lossy_string().into())
-        .collect_vec())
+    let png = Png::from_file_with(file, mode)?;
+    let envelope = match lsb::extract(&png, channels, scatter_key.map(str::as_bytes)) {
+        Ok(envelope) => envelope,
+        Err(PngError::NotAnEnvelope) => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(vec![envelope
+        .open_with_limit(passphrase.map(str::as_bytes), size_limit)
+        .context("Failed to read message 1")?])
+}
+
+/// Writes a PNG image to the output of `args`, or over `file` if there is none, without
+/// building it in memory first. Files are written to a temporary file that is renamed into
+/// place once complete, so the destination is never left half-written, and symlinks are
+/// followed to the file they point to. The permissions and modification time of `file`
+/// are kept, and a file being replaced is copied to a backup first if `args` asks for one. Returns where the image was written, `None` for stdout.
+/// With a dry run, the changes to the chunks of `original` are printed and nothing is written.
+/// `file` is read again to get them if no `original` is given.
+pub fn write_png<P>(
+    file: P,
+    original: Option<&Png>,
+    png: &Png,
+    args: &WriteArgs,
+) -> Result<Option<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let file = file.as_ref();
+    if args.dry_run {
+        match original {
+            Some(original) => print_diff(original, png),
+            None => print_diff(&Png::from_file_with(file, ParseMode::Lenient)?, png),
+        }
+        return Ok(None);
+    }
+    let dest = args.output.as_deref().unwrap_or(file);
+    if dest == Path::new("-") {
+        let mut stdout = io::stdout().lock();
+        png.write_to(&mut stdout)?;
+        stdout.flush()?;
+        return Ok(None);
+    }
+
+    // Replace the file a symlink points to rather than the symlink itself
+    let dest = match fs_err::symlink_metadata(dest) {
+        Ok(metadata) if metadata.file_type().is_symlink() => fs_err::canonicalize(dest)?,
+        _ => dest.to_path_buf(),
+    };
+    let metadata = fs_err::metadata(file)?;
+    let name = dest
+        .file_name()
+        .with_context(|| format!("\"{}\" is not a file path", dest.display()))?;
+    let tmp = dest.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    let write_tmp = || -> Result<()> {
+        let mut options = fs_err::OpenOptions::new();
+        options.write(true).create_new(true);
+        // Only readable by the current user until the permissions of `file` are applied
+        #[cfg(unix)]
+        fs_err::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut writer = BufWriter::new(options.open(&tmp)?);
+        png.write_to(&mut writer)?;
+        let tmp_file = writer.into_inner().map_err(|err| err.into_error())?;
+        tmp_file.set_permissions(metadata.permissions())?;
+        tmp_file.file().set_modified(metadata.modified()?)?;
+        tmp_file.sync_all()?;
+        Ok(())
+    };
+    if let Err(err) = write_tmp() {
+        let _ = fs_err::remove_file(&tmp);
+        return Err(err.context(format!("Failed to write \"{}\"", dest.display())));
+    }
+
+    if let (Some(suffix), true) = (&args.backup, dest.exists()) {
+        // The file being replaced may not be `file` with '--output'
+        let modified = fs_err::metadata(&dest)?.modified()?;
+        let mut backup = dest.as_os_str().to_owned();
+        backup.push(suffix);
+        fs_err::copy(&dest, &backup)?;
+        fs_err::OpenOptions::new()
+            .write(true)
+            .open(&backup)?
+            .file()
+            .set_modified(modified)?;
+    }
+    fs_err::rename(&tmp, &dest)?;
+    Ok(Some(dest))
+}
+
+/// Prints the chunks that differ between `old` and `new`, followed by the change in file size.
+fn print_diff(old: &Png, new: &Png) {
+    let describe = |idx: usize, chunk: &Chunk| {
+        format!("{} ({}), {} bytes", idx, chunk.chunk_type(), chunk.length())
+    };
+    let changes = old.diff(new);
+    for change in &changes {
+        match *change {
+            ChunkChange::Unchanged { .. } => {}
+            ChunkChange::Added { new: idx } => println!(
+                "{} {}",
+                style("+").green().bold(),
+                style(describe(idx, &new.chunks()[idx])).green()
+            ),
+            ChunkChange::Removed { old: idx } => println!(
+                "{} {}",
+                style("-").red().bold(),
+                style(describe(idx, &old.chunks()[idx])).red()
+            ),
+            ChunkChange::Moved {
+                old: from,
+                new: idx,
+            } => println!(
+                "{} {} {}",
+                style("~").yellow().bold(),
+                style(describe(idx, &new.chunks()[idx])).yellow(),
+                style(format!("moved from {}", from)).dim()
+            ),
+        }
+    }
+
+    let unchanged = changes
+        .iter()
+        .filter(|change| change.is_unchanged())
+        .count();
+    let (old_size, new_size) = (old.byte_len(), new.byte_len());
+    println!(
+        "\n{} {} unchanged chunk{}, {} -> {} bytes ({:+})",
+        style("Dry run:").cyan().bold(),
+        unchanged,
+        if unchanged == 1 { "" } else { "s" },
+        old_size,
+        new_size,
+        new_size as i64 - old_size as i64
+    );
+}
+
+/// Opens `text` in the editor named by `$VISUAL` or `$EDITOR`, falling back to `vi`,
+/// and returns the edited text. The temporary file is only readable by the current user
+/// and removed as soon as the editor exits.
+pub fn edit_text(text: &str) -> Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into());
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("The editor command is empty")?;
+
+    let path = env::temp_dir().join(format!("pngwhisper-{}.txt", std::process::id()));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .with_context(|| format!("Failed to create \"{}\"", path.display()))?;
+
+    let edited = Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Failed to run the editor '{}'", editor))
+        .and_then(|status| {
+            if !status.success() {
+                bail!("The editor '{}' exited with {}", editor, status)
+            }
+            Ok(fs_err::read_to_string(&path)?)
+        });
+    let _ = fs_err::remove_file(&path);
+
+    // Editors like to end files with a newline
+    let mut edited = edited?;
+    if !text.ends_with('\n') && edited.ends_with('\n') {
+        edited.pop();
+        if edited.ends_with('\r') {
+            edited.pop();
+        }
+    }
+    Ok(edited)
+}
+
+/// Writes the exact bytes of a decoded payload to `path`. If `path` is a directory,
+/// the payload's original file name is used inside of it.
+pub fn write_payload<P>(path: P, payload: &Payload) -> Result<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let mut path = path.as_ref().to_path_buf();
+    if path.is_dir() {
+        let name = match payload {
+            Payload::File {
+                name: Some(name), ..
+            } => Path::new(name)
+                .file_name()
+                .context("Invalid file name in payload")?
+                .to_owned(),
+            _ => bail!(
+                "The payload has no file name, an output file path is required instead of \"{}\"",
+                path.display()
+            ),
+        };
+        path.push(name);
+    }
+    fs_err::write(&path, payload.data())?;
+    Ok(path)
 }
 
-/// Remove a chunk from a PNG image.
-pub fn remove<P>(file: P, chunk_type: ChunkType) -> Result<Png>
+/// Which chunks `remove` removes.
+pub enum Selection {
+    /// The first chunk of a type.
+    First(ChunkType),
+    /// Every chunk of a type.
+    All(ChunkType),
+    Indices(Vec<usize>),
+    Range(IndexRange),
+}
+
+/// Removes the selected chunks from a PNG image, returning it along with the removed
+/// chunks. Critical chunks are only removed if `force` is set.
+pub fn remove<P>(
+    file: P,
+    selection: Selection,
+    force: bool,
+    mode: ParseMode,
+) -> Result<(Png, Vec<Chunk>)>
 where
     P: AsRef<Path>,
 {
-    let mut png = Png::from_file(file)?;
-    png.remove_chunk(&chunk_type)?;
-    Ok(png)
+    let mut png = Png::from_file_with(file, mode)?;
+    let len = png.chunks().len();
+    let check = |idx: usize, chunk: &Chunk| -> Result<()> {
+        if chunk.chunk_type().is_critical() && !force {
+            bail!(
+                "Refusing to remove the critical chunk '{}' at index {}, use '--force' to remove it anyway",
+                chunk.chunk_type(),
+                idx
+            )
+        }
+        Ok(())
+    };
+
+    let removed = match selection {
+        Selection::First(chunk_type) | Selection::All(chunk_type) => {
+            let matching = png
+                .chunks()
+                .iter()
+                .positions(|chunk| chunk.chunk_type() == &chunk_type)
+                .collect_vec();
+            let Some(&first) = matching.first() else {
+                bail!(PngError::ChunkNotFound(chunk_type))
+            };
+            check(first, &png.chunks()[first])?;
+            match selection {
+                Selection::All(_) => png.retain(|chunk| chunk.chunk_type() != &chunk_type),
+                _ => vec![png.remove_at(first)?],
+            }
+        }
+        Selection::Indices(mut indices) => {
+            indices.sort_unstable();
+            indices.dedup();
+            for &idx in &indices {
+                match png.chunks().get(idx) {
+                    Some(chunk) => check(idx, chunk)?,
+                    None => bail!(PngError::IndexOutOfBounds { index: idx, len }),
+                }
+            }
+            let mut removed = indices
+                .iter()
+                .rev()
+                .map(|&idx| png.remove_at(idx))
+                .collect::<Result<Vec<_>, _>>()?;
+            removed.reverse();
+            removed
+        }
+        Selection::Range(range) => {
+            let selected = png.chunks().get((Bound::Included(range.start), range.end));
+            for (i, chunk) in selected.unwrap_or_default().iter().enumerate() {
+                check(range.start + i, chunk)?;
+            }
+            png.remove_range(range)?
+        }
+    };
+    Ok((png, removed))
 }
 
-/// Prints all of the chunks in a PNG file.
-pub fn print_chunks<P>(file: P) -> Result<()>
+/// Strips the ancillary chunks matching `filter` from a PNG image, except those of the
+/// `keep` types. With `privacy`, only the chunks that may identify the image are stripped.
+pub fn strip<P>(
+    file: P,
+    keep: &[ChunkType],
+    privacy: bool,
+    filter: &ChunkFilter,
+    mode: ParseMode,
+) -> Result<(Png, Vec<Chunk>)>
 where
     P: AsRef<Path>,
 {
-    let png = Png::from_file(file)?;
-    for (i, chunk) in png.chunks().iter().enumerate() {
-        println!(
-            "{} \"{}\"",
-            style(format!("{} ({}):", i + 1, chunk.chunk_type()))
-                .yellow()
-                .bold(),
-            chunk.data_as_lossy_string()
+    let mut png = Png::from_file_with(file, mode)?;
+    let stripped = png.strip(|chunk| {
+        let chunk_type = chunk.chunk_type();
+        filter.matches(chunk_type)
+            && !keep.contains(chunk_type)
+            && (!privacy || chunk_type.is_identifying())
+    });
+    Ok((png, stripped))
+}
+
+/// Prints the image header followed by the chunks in a PNG file selected by `filter`.
+/// With `OutputFormat::Text`, the data of each chunk is shown as text or a hex dump
+/// depending on `view`, and cut off after `max_bytes` unless it is 0.
+pub fn print_chunks<P>(
+    file: P,
+    mode: ParseMode,
+    format: OutputFormat,
+    view: DataView,
+    max_bytes: usize,
+    filter: &ChunkFilter,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut reader = Png::open_chunks(file, mode)?;
+    let mut image = None;
+    let mut records = vec![];
+    let mut i = 0;
+    loop {
+        let offset = reader.offset();
+        let Some(chunk) = reader.next().transpose()? else {
+            break;
+        };
+        if i == 0 {
+            match (Ihdr::try_from(&chunk), format) {
+                (Ok(ihdr), OutputFormat::Text) => {
+                    println!("{} {}", style("Image:").cyan().bold(), ihdr)
+                }
+                (Err(err), OutputFormat::Text) => println!("{}", style(err).red().bold()),
+                (ihdr, _) => image = ihdr.ok().as_ref().map(ImageRecord::from),
+            }
+        }
+        if filter.matches(chunk.chunk_type()) {
+            match format {
+                OutputFormat::Text => println!("{}", format_chunk(i, &chunk, view, max_bytes)),
+                OutputFormat::Json => records.push(ChunkRecord::new(i, offset, &chunk)),
+                OutputFormat::Ndjson => output::print_line(&ChunkRecord::new(i, offset, &chunk))?,
+            }
+        }
+        i += 1;
+    }
+    if format == OutputFormat::Json {
+        output::print_line(&PrintDocument::new(
+            image,
+            records,
+            reader.trailing_data().len(),
+        ))?;
+    }
+    Ok(())
+}
+
+/// Formats a chunk for `print`, showing at most `max_bytes` of its data as text or as a
+/// hex dump, or all of it if `max_bytes` is 0.
+fn format_chunk(i: usize, chunk: &Chunk, view: DataView, max_bytes: usize) -> String {
+    let data = chunk.data();
+    let shown = match max_bytes {
+        0 => data,
+        max_bytes => &data[..data.len().min(max_bytes)],
+    };
+    let label = format!(
+        "{}{}",
+        style(format!("{} ({}):", i, chunk.chunk_type()))
+            .yellow()
+            .bold(),
+        match chunk.has_valid_crc() {
+            true => String::new(),
+            false => format!(" {}", style("[bad CRC]").red().bold()),
+        }
+    );
+    let hex = match view {
+        // NUL separates the keyword from the text in text chunks
+        DataView::Auto => data
+            .split(|&byte| byte == 0)
+            .any(|part| output::as_text(part).is_none()),
+        DataView::Text => false,
+        DataView::Hex => true,
+    };
+    let more = match data.len() - shown.len() {
+        0 => String::new(),
+        more => style(format!("... {} more bytes", more)).dim().to_string(),
+    };
+
+    if hex {
+        let mut lines = vec![format!(
+            "{} {}",
+            label,
+            style(format!("{} bytes", data.len())).dim()
+        )];
+        if !shown.is_empty() {
+            lines.push(output::hexdump(shown));
+        }
+        if !more.is_empty() {
+            lines.push(more);
+        }
+        lines.join("\n")
+    } else {
+        format!(
+            "{} \"{}\"{}",
+            label,
+            String::from_utf8_lossy(shown),
+            match more.is_empty() {
+                true => more,
+                false => format!(" {}", more),
+            }
         )
     }
+}
+
+/// Prints all of the text chunks in a PNG file. Malformed ones are reported on stderr
+/// and skipped.
+pub fn print_text_chunks<P>(file: P, mode: ParseMode) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    for (idx, chunk) in Png::open_chunks(file, mode)?.enumerate() {
+        let chunk = chunk?;
+        if TextChunk::is_text_chunk(chunk.chunk_type()) {
+            match TextChunk::try_from(&chunk) {
+                Ok(text) => println!(
+                    "{} {}",
+                    style(format!("{} ({}):", text.keyword(), chunk.chunk_type()))
+                        .yellow()
+                        .bold(),
+                    text.text()
+                ),
+                Err(err) => eprintln!(
+                    "{}",
+                    style(format!(
+                        "Skipping malformed '{}' chunk {}: {}",
+                        chunk.chunk_type(),
+                        idx,
+                        err
+                    ))
+                    .italic()
+                    .yellow()
+                ),
+            }
+        }
+    }
     Ok(())
 }
+
+/// Prints how many bytes of text can be hidden in a PNG file with each method,
+/// after the envelope and encryption overhead. Only the `IHDR` chunk is read.
+pub fn print_capacity<P>(file: P, mode: ParseMode) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let ihdr = match Png::open_chunks(file, mode)?.next().transpose()? {
+        Some(chunk) => Ihdr::try_from(&chunk)?,
+        None => bail!(PngError::ChunkNotFound(ChunkType::IHDR)),
+    };
+    println!("{} {}", style("Image:").cyan().bold(), ihdr);
+    println!(
+        "{}\n",
+        style("Usable bytes for a text message with each method:")
+            .italic()
+            .magenta()
+    );
+
+    let label = |method: String| {
+        style(format!("{:<10}", format!("{}:", method)))
+            .yellow()
+            .bold()
+    };
+    let usable = |capacity: usize, encrypted| {
+        capacity.saturating_sub(Envelope::overhead(Compression::None, encrypted))
+    };
+
+    // The image doesn't limit the chunk method, only the length fields of the chunks
+    // and of the envelope do
+    let single = Chunk::MAX_LENGTH;
+    let split = Envelope::HEADER_LEN + Envelope::MAX_BODY_LEN;
+    println!(
+        "{} {} bytes ({} encrypted) in a single chunk, {} bytes ({} encrypted) split across chunks",
+        label("chunk".into()),
+        usable(single, false),
+        usable(single, true),
+        usable(split, false),
+        usable(split, true),
+    );
+
+    let choices = lsb::channel_choices(ihdr.color_type());
+    if choices.is_empty() {
+        println!(
+            "{} {}",
+            label("lsb".into()),
+            style(format!("{} images are not supported", ihdr.color_type())).red()
+        );
+    }
+    for channels in choices {
+        match lsb::capacity(&ihdr, channels) {
+            Ok(capacity) => println!(
+                "{} {} bytes ({} encrypted)",
+                label(format!("lsb {}", channels)),
+                usable(capacity, false),
+                usable(capacity, true),
+            ),
+            Err(err) => {
+                println!("{} {}", label("lsb".into()), style(err).red());
+                break;
+            }
+        }
+    }
+
+    println!(
+        "\n{}",
+        style(
+            "Files take 10 more bytes plus the length of their name. \
+            Compression adds 1 byte, but usually saves a lot more."
+        )
+        .italic()
+        .dim()
+    );
+    Ok(())
+}
+
+/// Prints every spec violation found in a PNG file and returns the number of errors.
+/// The file is always parsed leniently, so that a missing `IEND` or trailing data
+/// is reported instead of aborting.
+pub fn lint<P>(file: P) -> Result<usize>
+where
+    P: AsRef<Path>,
+{
+    let png = Png::from_file_with(file, ParseMode::Lenient)?;
+    let findings = png.validate();
+    for finding in &findings {
+        let severity = match finding.severity() {
+            Severity::Error => style(format!("{}:", finding.severity())).red().bold(),
+            Severity::Warning => style(format!("{}:", finding.severity())).yellow().bold(),
+        };
+        match finding.index() {
+            Some(idx) => println!(
+                "{} {} {}",
+                severity,
+                style(format!("{} ({}):", idx, png.chunks()[idx].chunk_type())).bold(),
+                finding.issue()
+            ),
+            None => println!("{} {}", severity, finding.issue()),
+        }
+    }
+    Ok(findings.iter().filter(|finding| finding.is_error()).count())
+}
+
+/// Salvages the chunks of a damaged PNG file, fixing their CRCs, dropping any bytes
+/// that don't belong to a chunk and adding a missing `IEND`. Prints what was done and
+/// returns the image as it was recovered along with the repaired one, or `None` if there
+/// was nothing to repair.
+pub fn repair<P>(file: P) -> Result<Option<(Png, Png)>>
+where
+    P: AsRef<Path>,
+{
+    let recovery = Png::recover(&fs_err::read(file)?);
+    let has_iend = recovery
+        .png()
+        .chunks()
+        .last()
+        .is_some_and(|chunk| chunk.chunk_type() == &ChunkType::IEND);
+    if recovery.is_clean() && has_iend {
+        return Ok(None);
+    }
+
+    let report = |message: String| println!("{} {}", style("Repaired:").yellow().bold(), message);
+    if recovery.signature_damaged() {
+        report("Replaced the damaged PNG signature".into());
+    }
+    for range in recovery.skipped() {
+        report(format!(
+            "Skipped {} bytes of garbage at offset {}",
+            range.len(),
+            range.start
+        ));
+    }
+
+    let original = recovery.png().clone();
+    let mut png = recovery.into_png();
+    for idx in png.fix_crcs() {
+        report(format!(
+            "Fixed the CRC of chunk {} ({})",
+            idx,
+            png.chunks()[idx].chunk_type()
+        ));
+    }
+    let trailing = png.take_trailing_data();
+    if !trailing.is_empty() {
+        report(format!(
+            "Dropped {} bytes after the last chunk",
+            trailing.len()
+        ));
+    }
+    if !has_iend {
+        png.append_chunk(Chunk::new(ChunkType::IEND, vec![]));
+        report("Added the missing 'IEND' chunk".into());
+    }
+    Ok(Some((original, png)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(kind: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(kind).unwrap(), data.to_vec())
+    }
+
+    fn png(kinds: &[&str]) -> Png {
+        Png::from_chunks(kinds.iter().map(|kind| chunk(kind, b"")).collect())
+    }
+
+    fn write_args(output: Option<&Path>, backup: Option<&str>) -> WriteArgs {
+        WriteArgs {
+            output: output.map(Path::to_path_buf),
+            backup: backup.map(String::from),
+            dry_run: false,
+        }
+    }
+
+    /// Writes `png` to `path` with a modification time in the past.
+    fn write_old(path: &Path, png: &Png) -> std::time::SystemTime {
+        let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1 << 30);
+        fs_err::write(path, png.as_bytes()).unwrap();
+        fs_err::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .file()
+            .set_modified(modified)
+            .unwrap();
+        modified
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names = fs_err::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect_vec();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_write_png_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("image.png");
+        let modified = write_old(&file, &png(&["IHDR", "IEND"]));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs_err::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        let new = png(&["IHDR", "ruSt", "IEND"]);
+        let written = write_png(&file, None, &new, &write_args(None, None)).unwrap();
+        assert_eq!(written.as_deref(), Some(file.as_path()));
+        assert_eq!(fs_err::read(&file).unwrap(), new.as_bytes());
+        let metadata = fs_err::metadata(&file).unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        }
+        // The temporary file has been renamed into place
+        assert_eq!(file_names(dir.path()), ["image.png"]);
+    }
+
+    #[test]
+    fn test_write_png_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let (file, output) = (dir.path().join("in.png"), dir.path().join("out.png"));
+        let old = png(&["IHDR", "IEND"]);
+        write_old(&file, &old);
+
+        let new = png(&["IHDR", "ruSt", "IEND"]);
+        let written = write_png(&file, None, &new, &write_args(Some(&output), None)).unwrap();
+        assert_eq!(written.as_deref(), Some(output.as_path()));
+        assert_eq!(fs_err::read(&file).unwrap(), old.as_bytes());
+        assert_eq!(fs_err::read(&output).unwrap(), new.as_bytes());
+        assert_eq!(file_names(dir.path()), ["in.png", "out.png"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_png_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let (file, link) = (dir.path().join("image.png"), dir.path().join("link.png"));
+        write_old(&file, &png(&["IHDR", "IEND"]));
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        let new = png(&["IHDR", "ruSt", "IEND"]);
+        let written = write_png(&link, None, &new, &write_args(None, None)).unwrap();
+        assert_eq!(written, Some(fs_err::canonicalize(&file).unwrap()));
+        assert!(fs_err::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs_err::read(&file).unwrap(), new.as_bytes());
+        assert_eq!(file_names(dir.path()), ["image.png", "link.png"]);
+    }
+
+    #[test]
+    fn test_write_png_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("image.png");
+        let old = png(&["IHDR", "IEND"]);
+        let modified = write_old(&file, &old);
+        let new = png(&["IHDR", "ruSt", "IEND"]);
+        write_png(&file, None, &new, &write_args(None, Some(".bak"))).unwrap();
+        let backup = dir.path().join("image.png.bak");
+        assert_eq!(fs_err::read(&backup).unwrap(), old.as_bytes());
+        assert_eq!(
+            fs_err::metadata(&backup).unwrap().modified().unwrap(),
+            modified
+        );
+        assert_eq!(fs_err::read(&file).unwrap(), new.as_bytes());
+
+        // With an output, the file being replaced is backed up, not the input
+        let output = dir.path().join("out.png");
+        let previous = png(&["IHDR", "IDAT", "IEND"]);
+        let previous_modified = write_old(&output, &previous);
+        write_png(&file, None, &old, &write_args(Some(&output), Some("~"))).unwrap();
+        let backup = dir.path().join("out.png~");
+        assert_eq!(fs_err::read(&backup).unwrap(), previous.as_bytes());
+        assert_eq!(
+            fs_err::metadata(&backup).unwrap().modified().unwrap(),
+            previous_modified
+        );
+        assert_eq!(fs_err::read(&output).unwrap(), old.as_bytes());
+
+        // Nothing to back up if the output doesn't exist yet
+        let fresh = dir.path().join("fresh.png");
+        write_png(&file, None, &old, &write_args(Some(&fresh), Some(".bak"))).unwrap();
+        assert!(!dir.path().join("fresh.png.bak").exists());
+    }
+
+    #[test]
+    fn test_find_message() {
+        let kind = ChunkType::from_str("wsPr").unwrap();
+        let mut image = png(&["IHDR", "IDAT", "IEND"]);
+        assert!(find_message(&image, kind).unwrap().is_none());
+
+        let payload = Payload::Text("a message long enough to be split".into());
+        let position = ChunkPosition::BeforeIdat;
+        let chunks = |compression, max_chunk_size| {
+            message_chunks(&payload, kind, compression, None, max_chunk_size).unwrap()
+        };
+        encode(
+            &mut image,
+            chunks(Compression::Deflate, 40),
+            position,
+            false,
+        )
+        .unwrap();
+        let stored = find_message(&image, kind).unwrap().unwrap();
+        assert_eq!(stored.max_chunk_size, 40);
+        assert_eq!(stored.compression(), Compression::Deflate);
+        assert_eq!(stored.envelope.open(None).unwrap(), payload);
+
+        encode(&mut image, chunks(Compression::None, 1000), position, false).unwrap();
+        assert!(find_message(&image, kind).is_err());
+
+        // Replacing leaves a single message where the first one was
+        encode(&mut image, chunks(Compression::None, 40), position, true).unwrap();
+        let stored = find_message(&image, kind).unwrap().unwrap();
+        assert_eq!(stored.max_chunk_size, 40);
+        assert_eq!(stored.compression(), Compression::None);
+        assert_eq!(image.chunks()[1].chunk_type(), &kind);
+    }
+
+    #[test]
+    fn test_encode_replace() {
+        let kind = ChunkType::from_str("wsPr").unwrap();
+        let payload = Payload::Text("a message long enough to be split".into());
+        let chunks = message_chunks(&payload, kind, Compression::None, None, 30).unwrap();
+        assert!(chunks.len() > 1);
+
+        let mut image = png(&["IHDR", "IDAT", "IEND"]);
+        encode(&mut image, chunks.clone(), ChunkPosition::BeforeIend, true).unwrap();
+        assert_eq!(image.chunks().len(), 3 + chunks.len());
+        assert_eq!(image.chunks()[2..2 + chunks.len()], chunks);
+
+        let mut image = png(&["IHDR", "wsPr", "IDAT", "wsPr", "IEND"]);
+        encode(&mut image, chunks.clone(), ChunkPosition::BeforeIend, true).unwrap();
+        assert_eq!(image.chunks().len(), 3 + chunks.len());
+        assert_eq!(image.chunks()[1..1 + chunks.len()], chunks);
+        assert_eq!(
+            image.chunks()[1 + chunks.len()].chunk_type(),
+            &ChunkType::IDAT
+        );
+    }
+
+    #[test]
+    fn test_format_chunk_text() {
+        console::set_colors_enabled(false);
+        let text = chunk("tEXt", b"Comment\0hello there");
+        assert_eq!(
+            format_chunk(0, &text, DataView::Auto, 0),
+            "0 (tEXt): \"Comment\u{0}hello there\""
+        );
+        assert_eq!(
+            format_chunk(2, &text, DataView::Text, 7),
+            "2 (tEXt): \"Comment\" ... 12 more bytes"
+        );
+    }
+
+    #[test]
+    fn test_format_chunk_hex() {
+        console::set_colors_enabled(false);
+        let binary = chunk("ruSt", &[0xff; 20]);
+        assert_eq!(
+            format_chunk(0, &binary, DataView::Auto, 16),
+            format!(
+                "0 (ruSt): 20 bytes\n00000000  {}  {}  |{}|\n... 4 more bytes",
+                ["ff"; 8].join(" "),
+                ["ff"; 8].join(" "),
+                ".".repeat(16)
+            )
+        );
+        assert_eq!(
+            format_chunk(0, &chunk("ruSt", b"text"), DataView::Hex, 0),
+            format!(
+                "0 (ruSt): 4 bytes\n00000000  74 65 78 74{}  |text|",
+                " ".repeat(37)
+            )
+        );
+        assert_eq!(
+            format_chunk(0, &chunk("IEND", b""), DataView::Hex, 0),
+            "0 (IEND): 0 bytes"
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use crate::error::{PngError, Result};
+use argon2::Argon2;
+use chacha20::{
+    cipher::{KeyIvInit, StreamCipher},
+    ChaCha20,
+};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+
+/// Fixed salt for deriving the key of a `Permutation`, which has nowhere to store a random one.
+const SHUFFLE_SALT: &[u8] = b"pngwhisper shuffle";
+
+/// Length of the random salt used for key derivation.
+pub const SALT_LEN: usize = 16;
+/// Length of the XChaCha20-Poly1305 nonce.
+pub const NONCE_LEN: usize = 24;
+/// Length of the Poly1305 authentication tag appended to the ciphertext.
+pub const TAG_LEN: usize = 16;
+/// Number of bytes `encrypt` adds on top of the plaintext.
+pub const OVERHEAD: usize = SALT_LEN + NONCE_LEN + TAG_LEN;
+
+/// Derives a 256-bit key from a passphrase using Argon2id with its default parameters.
+fn derive_key(passphrase: &[u8], salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase, salt, &mut key)
+        .map_err(|err| PngError::Encryption(err.to_string()))?;
+    Ok(key)
+}
+
+/// Seals `plaintext` with a key derived from `passphrase`. The associated data `aad`
+/// isn't stored, but decryption fails unless the same is given again.
+///
+/// The returned bytes are laid out as follows:
+/// 1. Salt used for key derivation *(16 bytes)*
+/// 2. Nonce *(24 bytes)*
+/// 3. Ciphertext followed by the authentication tag *(`plaintext.len()` + 16 bytes)*
+pub fn encrypt(passphrase: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|err| PngError::Encryption(err.to_string()))?;
+
+    Ok(salt.into_iter().chain(nonce).chain(ciphertext).collect())
+}
+
+/// Opens bytes produced by `encrypt` with the same associated data. Fails if the
+/// passphrase is wrong or the data has been tampered with.
+pub fn decrypt(passphrase: &[u8], sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < OVERHEAD {
+        return Err(PngError::Decryption);
+    }
+    let (salt, rest) = sealed.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+    cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| PngError::Decryption)
+}
+
+/// A permutation of `0..len` that only depends on `key`, so that the same key always
+/// produces the same order. Its elements are drawn one at a time by a Fisher-Yates
+/// shuffle driven by a ChaCha20 keystream, keyed with Argon2id. Only the swaps made so
+/// far are stored, so drawing a few elements of a huge permutation is cheap.
+pub struct Permutation {
+    cipher: ChaCha20,
+    len: usize,
+    drawn: usize,
+    /// Elements that have been swapped to a position not yet drawn.
+    swapped: HashMap<usize, usize>,
+}
+
+impl Permutation {
+    pub fn new(key: &[u8], len: usize) -> Result<Permutation> {
+        Ok(Permutation {
+            cipher: ChaCha20::new(&derive_key(key, SHUFFLE_SALT)?, &Default::default()),
+            len,
+            drawn: 0,
+            swapped: HashMap::new(),
+        })
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut buf = [0u8; 8];
+        self.cipher.apply_keystream(&mut buf);
+        u64::from_le_bytes(buf)
+    }
+}
+
+impl Iterator for Permutation {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.drawn == self.len {
+            return None;
+        }
+        let i = self.drawn;
+        self.drawn += 1;
+
+        // The modulo bias is at most `len / 2^64`, which is negligible.
+        let j = i + (self.next_u64() % (self.len - i) as u64) as usize;
+        let at_i = self.swapped.remove(&i).unwrap_or(i);
+        if j == i {
+            return Some(at_i);
+        }
+        Some(self.swapped.insert(j, at_i).unwrap_or(j))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.len - self.drawn;
+        (left, Some(left))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let sealed = encrypt(b"hunter2", b"Secret message", b"header").unwrap();
+        assert_eq!(sealed.len(), b"Secret message".len() + OVERHEAD);
+        assert_eq!(
+            decrypt(b"hunter2", &sealed, b"header").unwrap(),
+            b"Secret message"
+        );
+    }
+
+    #[test]
+    fn test_decrypt_wrong_passphrase() {
+        let sealed = encrypt(b"hunter2", b"Secret message", b"").unwrap();
+        assert!(decrypt(b"hunter3", &sealed, b"").is_err());
+    }
+
+    #[test]
+    fn test_decrypt_tampered() {
+        let mut sealed = encrypt(b"hunter2", b"Secret message", b"header").unwrap();
+        assert!(decrypt(b"hunter2", &sealed, b"Header").is_err());
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(decrypt(b"hunter2", &sealed, b"header").is_err());
+        assert!(decrypt(b"hunter2", &sealed[..OVERHEAD - 1], b"header").is_err());
+    }
+
+    #[test]
+    fn test_permutation() {
+        let original: Vec<usize> = (0..1000).collect();
+        let permuted = |key: &[u8]| Permutation::new(key, 1000).unwrap().collect::<Vec<_>>();
+
+        let items = permuted(b"hunter2");
+        assert_ne!(items, original);
+        assert_eq!(items, permuted(b"hunter2"));
+        assert_ne!(items, permuted(b"hunter3"));
+
+        let mut sorted = items;
+        sorted.sort_unstable();
+        assert_eq!(sorted, original);
+    }
+
+    #[test]
+    fn test_huge_permutation() {
+        let drawn: Vec<usize> = Permutation::new(b"hunter2", usize::MAX)
+            .unwrap()
+            .take(1000)
+            .collect();
+        assert_eq!(drawn.iter().unique().count(), 1000);
+    }
+}
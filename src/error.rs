@@ -0,0 +1,121 @@
+use std::io;
+
+use itertools::Itertools;
+
+use crate::png::chunk_type::ChunkType;
+
+pub type Result<T, E = PngError> = std::result::Result<T, E>;
+
+/// Everything that can go wrong while reading, modifying or writing a PNG
+/// and the messages hidden inside of it.
+#[derive(Debug, thiserror::Error)]
+pub enum PngError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Invalid PNG signature")]
+    InvalidSignature,
+    #[error("Truncated chunk at offset {offset}")]
+    TruncatedChunk { offset: u64 },
+    #[error("Missing 'IEND' chunk, the file may be truncated")]
+    MissingIend,
+    #[error("{len} bytes of trailing data after 'IEND' at offset {offset}")]
+    TrailingData { offset: u64, len: u64 },
+    /// `expected` is the CRC computed over the chunk, `actual` the one stored in it.
+    #[error("Incorrect CRC for '{chunk_type}' chunk, expected {expected:#010x} but found {actual:#010x}")]
+    CrcMismatch {
+        chunk_type: ChunkType,
+        expected: u32,
+        actual: u32,
+    },
+    #[error("Invalid chunk type '{chunk_type}': {reason}")]
+    InvalidChunkType {
+        chunk_type: String,
+        reason: &'static str,
+    },
+    #[error("Length {length} of '{chunk_type}' chunk exceeds the maximum of 2^31 - 1 bytes")]
+    ChunkTooLong { chunk_type: ChunkType, length: u32 },
+    #[error("Invalid 'IHDR' chunk: {0}")]
+    InvalidIhdr(String),
+    #[error("Invalid text chunk: {0}")]
+    InvalidText(String),
+    #[error("No text chunk with keyword '{0}' found")]
+    TextNotFound(String),
+    #[error("Unsupported image: {0}")]
+    UnsupportedImage(String),
+    #[error("Invalid image data: {0}")]
+    InvalidImageData(String),
+    #[error("Invalid channels '{0}', expected a combination of 'r', 'g', 'b' and 'a'")]
+    InvalidChannels(String),
+    #[error("The message needs {needed} bytes but the image can only hold {available}, {} bytes too many", .needed - .available)]
+    InsufficientCapacity { needed: usize, available: usize },
+    #[error("'{0}' not found")]
+    ChunkNotFound(ChunkType),
+    #[error("Index {index} is out of bounds for {len} chunks")]
+    IndexOutOfBounds { index: usize, len: usize },
+    #[error("Invalid range of chunks {start}..{end}, the start is after the end")]
+    ReversedRange { start: usize, end: usize },
+    #[error("Invalid position '{0}', expected one of 'before-iend', 'after-ihdr', 'before-idat' or a chunk index")]
+    InvalidPosition(String),
+    #[error(transparent)]
+    InvalidUtf8(#[from] std::str::Utf8Error),
+    #[error("Invalid payload: {0}")]
+    InvalidPayload(String),
+    #[error("Unknown content type {0}")]
+    UnknownContentType(u8),
+    #[error("Not a pngwhisper envelope")]
+    NotAnEnvelope,
+    #[error("Unsupported envelope version {found}, only version {supported} is supported")]
+    UnsupportedVersion { found: u8, supported: u8 },
+    #[error("Invalid envelope: {0}")]
+    InvalidEnvelope(String),
+    #[error("Incorrect envelope checksum, expected {expected:#010x} but found {actual:#010x}")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+    #[error("Invalid fragment: {0}")]
+    InvalidFragment(String),
+    #[error("Missing {} of {count}", describe_missing(.missing, *.more))]
+    MissingFragments {
+        /// The first few missing indices, starting at 0.
+        missing: Vec<u32>,
+        /// How many more fragments are missing beyond those listed.
+        more: u32,
+        count: u32,
+    },
+    #[error("Duplicate fragment {} of {count}", .index + 1)]
+    DuplicateFragment { index: u32, count: u32 },
+    #[error("Failed to read message {index}")]
+    Message {
+        /// Position of the message, starting at 1.
+        index: usize,
+        #[source]
+        source: Box<PngError>,
+    },
+    #[error("Unknown compression algorithm '{0}'")]
+    UnknownCompression(String),
+    #[error("Decompressed data exceeds the limit of {0} bytes")]
+    DecompressionLimit(usize),
+    #[error("{0} is too large")]
+    TooLarge(&'static str),
+    #[error("Encryption failed: {0}")]
+    Encryption(String),
+    #[error("Decryption failed: wrong passphrase or the data has been tampered with")]
+    Decryption,
+    #[error("The message is encrypted, a passphrase is required")]
+    PassphraseRequired,
+    #[error("The message isn't encrypted even though a passphrase was given, it may have been tampered with")]
+    NotEncrypted,
+}
+
+/// Lists missing fragments by their position starting at 1, e.g. "fragments 2, 5 and 3 more".
+fn describe_missing(missing: &[u32], more: u32) -> String {
+    let plural = if missing.len() == 1 && more == 0 {
+        ""
+    } else {
+        "s"
+    };
+    let listed = missing.iter().map(|i| (i + 1).to_string()).join(", ");
+    if more > 0 {
+        format!("fragment{plural} {listed} and {more} more")
+    } else {
+        format!("fragment{plural} {listed}")
+    }
+}
@@ -1,2 +1,4 @@
+pub mod crypto;
+pub mod error;
 pub mod png;
 pub mod utils;
@@ -1,108 +1,456 @@
-use std::io;
+use std::{
+    fmt::Display,
+    io::{self, Write},
+};
 
 use anyhow::{bail, Result};
 use clap::{CommandFactory, Parser};
-use commands::{decode, encode, print_chunks, remove};
+use commands::{
+    decode, decode_lsb, edit_text, encode, encode_lsb, find_message, lint, message_chunks,
+    print_capacity, print_chunks, print_text_chunks, remove, repair, strip, write_payload,
+    write_png,
+};
 use console::style;
+use itertools::Itertools;
+use pngwhisper::{
+    error::PngError,
+    png::{
+        chunk::Chunk, compression::Compression, payload::Payload, text::TextChunk, ChunkPosition,
+        ParseMode, Png,
+    },
+};
 
-use crate::args::Cli;
+use crate::{
+    args::{Cli, Method, OutputFormat, TextCommands, WriteArgs},
+    commands::{Selection, StoredMessage},
+};
 
 mod args;
 mod commands;
+mod output;
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let mode = if cli.lenient {
+        ParseMode::Lenient
+    } else {
+        ParseMode::Strict
+    };
 
     match cli.command {
         args::Commands::Encode {
             file,
-            message,
+            input,
+            method,
+            channels,
+            scatter,
             kind,
+            position,
+            compress,
+            encrypt,
+            max_chunk_size,
+            replace,
+            passphrase,
+            write,
         } => {
             let kind = kind.unwrap();
-            eprintln!(
-                "{}",
-                style(format!("Using '{}' chunk type...", kind))
-                    .italic()
-                    .magenta()
-            );
-            fs_err::write(&file, encode(&file, &message, kind)?.as_bytes())?;
-            eprintln!(
-                "{}",
-                style("The message has been successfully encoded into the PNG file.")
-                    .green()
-                    .bold()
+            let position = position.unwrap();
+            let compress = compress.unwrap();
+            let payload = input.read()?;
+            if scatter && method != Method::Lsb {
+                bail!("'--scatter' requires '--method lsb'")
+            }
+            if replace && method != Method::Chunk {
+                bail!("'--replace' requires '--method chunk', 'lsb' always replaces the message")
+            }
+            let secret = (encrypt || scatter)
+                .then(|| passphrase.read(true))
+                .transpose()?;
+            let passphrase = secret.as_deref().filter(|_| encrypt);
+            let scatter_key = secret.as_deref().filter(|_| scatter);
+            let mut png = Png::from_file_with(&file, mode)?;
+            warn_trailing_data(&png);
+            match method {
+                Method::Chunk => {
+                    eprintln!(
+                        "{}",
+                        style(format!("Using '{}' chunk type...", kind))
+                            .italic()
+                            .magenta()
+                    );
+                    let chunks =
+                        message_chunks(&payload, kind, compress, passphrase, max_chunk_size)?;
+                    encode(&mut png, chunks, position, replace)?;
+                }
+                Method::Lsb => {
+                    let channels = channels.unwrap();
+                    eprintln!(
+                        "{}",
+                        style(format!("Using the '{}' channels...", channels))
+                            .italic()
+                            .magenta()
+                    );
+                    encode_lsb(
+                        &mut png,
+                        &payload,
+                        channels,
+                        compress,
+                        passphrase,
+                        scatter_key,
+                    )?;
+                }
+            }
+            write_png(&file, None, &png, &write)?;
+            report_success(
+                &write,
+                "The message has been successfully encoded into the PNG file.",
             );
         }
-        args::Commands::Decode { file, kind } => {
+        args::Commands::Edit {
+            file,
+            kind,
+            compress,
+            encrypt,
+            max_chunk_size,
+            passphrase,
+            write,
+        } => {
             let kind = kind.unwrap();
-            let decoded_chunks = decode(&file, kind)?;
-            if decoded_chunks.is_empty() {
-                bail!(
-                    "No chunks of type '{}' were found in \"{}\"",
+            let mut png = Png::from_file_with(&file, mode)?;
+            warn_trailing_data(&png);
+            let stored = find_message(&png, kind)?;
+            let encrypted = stored.as_ref().is_some_and(|s| s.envelope.is_encrypted());
+            // A passphrase that will encrypt the message for the first time is confirmed
+            let passphrase = (encrypt || encrypted)
+                .then(|| passphrase.read(!encrypted))
+                .transpose()?;
+            let text = match &stored {
+                None => String::new(),
+                Some(stored) => match stored.envelope.open_with_limit(
+                    passphrase
+                        .as_deref()
+                        .filter(|_| stored.envelope.is_encrypted())
+                        .map(str::as_bytes),
+                    Compression::DEFAULT_LIMIT,
+                )? {
+                    Payload::Text(text) => text,
+                    Payload::File { .. } => bail!(
+                        "The message in chunks of type '{}' is a file and can't be edited as text",
+                        kind
+                    ),
+                },
+            };
+
+            let edited = edit_text(&text)?;
+            if edited == text {
+                eprintln!(
+                    "{}",
+                    style("The message is unchanged, nothing has been written.")
+                        .italic()
+                        .magenta()
+                );
+            } else {
+                // Store the message the way it was unless asked otherwise
+                let compress = compress
+                    .or(stored.as_ref().map(StoredMessage::compression))
+                    .unwrap_or_default();
+                let max_chunk_size = max_chunk_size
+                    .or(stored.as_ref().map(|stored| stored.max_chunk_size))
+                    .unwrap_or(Chunk::MAX_LENGTH);
+                let chunks = message_chunks(
+                    &Payload::Text(edited),
                     kind,
+                    compress,
+                    passphrase.as_deref(),
+                    max_chunk_size,
+                )?;
+                encode(&mut png, chunks, ChunkPosition::default(), true)?;
+                write_png(&file, None, &png, &write)?;
+                report_success(
+                    &write,
+                    "The message has been successfully updated in the PNG file.",
+                );
+            }
+        }
+        args::Commands::Decode {
+            file,
+            method,
+            channels,
+            scatter,
+            kind,
+            decrypt,
+            passphrase,
+            output,
+            raw,
+            size_limit,
+            format,
+        } => {
+            let kind = kind.unwrap();
+            if scatter && method != Method::Lsb {
+                bail!("'--scatter' requires '--method lsb'")
+            }
+            let secret = (decrypt || scatter)
+                .then(|| passphrase.read(false))
+                .transpose()?;
+            let passphrase = secret.as_deref().filter(|_| decrypt);
+            let scatter_key = secret.as_deref().filter(|_| scatter);
+            let channels = channels.unwrap();
+            let (decoded_chunks, location) = match method {
+                Method::Chunk => (
+                    decode(&file, kind, passphrase, size_limit, mode)?,
+                    format!("chunks of type '{}'", kind),
+                ),
+                Method::Lsb => (
+                    decode_lsb(&file, channels, passphrase, scatter_key, size_limit, mode)?,
+                    format!("the '{}' channels", channels),
+                ),
+            };
+            if format != OutputFormat::Text {
+                output::print_messages(&decoded_chunks, format)?;
+            } else if decoded_chunks.is_empty() {
+                bail!(
+                    "No messages in {} were found in \"{}\"",
+                    location,
                     file.display()
                 )
+            } else if output.is_some() || raw {
+                let [payload] = decoded_chunks.as_slice() else {
+                    bail!(
+                        "Found {} messages in {}, only a single message can be written out",
+                        decoded_chunks.len(),
+                        location
+                    )
+                };
+                if let Some(output) = output {
+                    let path = write_payload(output, payload)?;
+                    eprintln!(
+                        "{}",
+                        style(format!(
+                            "The message has been written to \"{}\".",
+                            path.display()
+                        ))
+                        .green()
+                        .bold()
+                    );
+                } else {
+                    io::stdout().write_all(payload.data())?;
+                }
             } else {
                 eprintln!(
                     "{}",
                     style(format!(
-                        "Found {} chunk{} of type '{}'...\n",
+                        "Found {} message{} in {}...\n",
                         decoded_chunks.len(),
                         if decoded_chunks.len() == 1 { "" } else { "s" },
-                        kind
+                        location
                     ))
                     .italic()
                     .magenta()
                 );
                 for (i, chunk) in decoded_chunks.iter().enumerate() {
-                    println!(
-                        "{} \"{}\"",
-                        style(format!("{}:", i + 1)).yellow().bold(),
-                        chunk
-                    )
+                    println!("{} {}", style(format!("{}:", i + 1)).yellow().bold(), chunk)
                 }
             }
         }
-        args::Commands::Remove { file, kind, all } => {
+        args::Commands::Remove {
+            file,
+            kind,
+            all,
+            index,
+            range,
+            force,
+            write,
+        } => {
             let kind = kind.unwrap();
-            eprintln!(
-                "{}",
-                style(format!("Using '{}' chunk type...", kind))
-                    .italic()
-                    .magenta()
-            );
+            let selection = match (range, index.is_empty(), all) {
+                (Some(range), _, _) => Selection::Range(range),
+                (None, false, _) => Selection::Indices(index),
+                (None, true, true) => Selection::All(kind),
+                (None, true, false) => Selection::First(kind),
+            };
+            if let Selection::First(_) | Selection::All(_) = selection {
+                eprintln!(
+                    "{}",
+                    style(format!("Using '{}' chunk type...", kind))
+                        .italic()
+                        .magenta()
+                );
+            }
 
-            if all {
-                let mut png = remove(&file, kind)?;
-                loop {
-                    if png.remove_chunk(&kind).is_err() {
-                        fs_err::write(&file, png.as_bytes())?;
-                        eprintln!(
-                            "{}",
-                            style(
-                                "All matching chunks have been \
-                        successfully removed from the PNG file."
-                            )
-                            .green()
-                            .bold()
-                        );
-                        break;
+            let (png, removed) = remove(&file, selection, force, mode)?;
+            warn_trailing_data(&png);
+            write_png(&file, None, &png, &write)?;
+            report_success(
+                &write,
+                format!(
+                    "{} chunk{} successfully removed from the PNG file.",
+                    removed.len(),
+                    if removed.len() == 1 {
+                        " has been"
+                    } else {
+                        "s have been"
                     }
-                }
-            } else {
-                fs_err::write(&file, remove(&file, kind)?.as_bytes())?;
+                ),
+            );
+        }
+        args::Commands::Strip {
+            file,
+            keep,
+            privacy,
+            filter,
+            write,
+        } => {
+            let (png, stripped) = strip(&file, &keep, privacy, &filter, mode)?;
+            if stripped.is_empty() {
                 eprintln!(
                     "{}",
-                    style("One matching chunk has been successfully removed from the PNG file.")
-                        .green()
-                        .bold()
+                    style("No chunks to strip, nothing has been written.")
+                        .italic()
+                        .magenta()
+                );
+            } else {
+                warn_trailing_data(&png);
+                write_png(&file, None, &png, &write)?;
+                report_success(
+                    &write,
+                    format!(
+                        "{} chunk{} successfully stripped from the PNG file: {}.",
+                        stripped.len(),
+                        if stripped.len() == 1 {
+                            " has been"
+                        } else {
+                            "s have been"
+                        },
+                        stripped
+                            .iter()
+                            .map(|chunk| chunk.chunk_type().to_string())
+                            .unique()
+                            .join(", ")
+                    ),
                 );
             }
         }
-        args::Commands::Print { file } => {
-            print_chunks(file)?;
+        args::Commands::Print {
+            file,
+            format,
+            view,
+            max_bytes,
+            filter,
+        } => {
+            print_chunks(file, mode, format, view, max_bytes, &filter)?;
+        }
+        args::Commands::Capacity { file } => {
+            print_capacity(file, mode)?;
+        }
+        args::Commands::Lint { file } => {
+            if mode == ParseMode::Lenient {
+                bail!("'lint' always reads files leniently to report their damage, '--lenient' can't be used with it")
+            }
+            let errors = lint(&file)?;
+            if errors > 0 {
+                bail!(
+                    "Found {} error{} in \"{}\"",
+                    errors,
+                    if errors == 1 { "" } else { "s" },
+                    file.display()
+                )
+            }
+            eprintln!("{}", style("The PNG file follows the spec.").green().bold());
         }
+        args::Commands::Repair { file, write } => match repair(&file)? {
+            Some((original, png)) => {
+                if let Some(path) = write_png(&file, Some(&original), &png, &write)? {
+                    eprintln!(
+                        "{}",
+                        style(format!(
+                            "The repaired PNG file has been written to \"{}\".",
+                            path.display()
+                        ))
+                        .green()
+                        .bold()
+                    );
+                }
+            }
+            None => eprintln!(
+                "{}",
+                style("The PNG file isn't damaged, nothing to repair.")
+                    .italic()
+                    .magenta()
+            ),
+        },
+        args::Commands::Text { command } => match command {
+            TextCommands::List { file } => {
+                print_text_chunks(file, mode)?;
+            }
+            TextCommands::Get { file, keyword } => {
+                let png = Png::from_file_with(&file, mode)?;
+                let texts = png
+                    .text_chunks()
+                    .into_iter()
+                    .flatten()
+                    .filter(|text| text.keyword() == keyword)
+                    .collect_vec();
+                if texts.is_empty() {
+                    bail!(PngError::TextNotFound(keyword))
+                }
+                for text in texts {
+                    println!("{}", text.text())
+                }
+            }
+            TextCommands::Set {
+                file,
+                keyword,
+                text,
+                compress,
+                international,
+                language,
+                translated_keyword,
+                position,
+                write,
+            } => {
+                let mut text = TextChunk::new(keyword, text)?.with_compression(compress);
+                if international
+                    || language.is_some()
+                    || translated_keyword.is_some()
+                    || !TextChunk::is_latin1(text.text())
+                {
+                    text = text.with_language(
+                        language.unwrap_or_default(),
+                        translated_keyword.unwrap_or_default(),
+                    )?;
+                }
+                let mut png = Png::from_file_with(&file, mode)?;
+                warn_trailing_data(&png);
+                png.set_text(&text, position.unwrap())?;
+                write_png(&file, None, &png, &write)?;
+                report_success(
+                    &write,
+                    format!(
+                        "The text has been stored in the PNG file as a '{}' chunk.",
+                        text.chunk_type()
+                    ),
+                );
+            }
+            TextCommands::Remove {
+                file,
+                keyword,
+                write,
+            } => {
+                let mut png = Png::from_file_with(&file, mode)?;
+                warn_trailing_data(&png);
+                let removed = png.remove_text(&keyword)?;
+                write_png(&file, None, &png, &write)?;
+                report_success(
+                    &write,
+                    format!(
+                        "{} text chunk{} with keyword '{}' removed from the PNG file.",
+                        removed.len(),
+                        if removed.len() == 1 { "" } else { "s" },
+                        keyword
+                    ),
+                );
+            }
+        },
         args::Commands::Completions { shell } => {
             clap_complete::generate(
                 shell,
@@ -115,3 +463,24 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Prints a success message, unless nothing was written because of `--dry-run`.
+fn report_success(write: &WriteArgs, message: impl Display) {
+    if !write.dry_run {
+        eprintln!("{}", style(message).green().bold());
+    }
+}
+
+fn warn_trailing_data(png: &Png) {
+    if !png.trailing_data().is_empty() {
+        eprintln!(
+            "{}",
+            style(format!(
+                "Preserving {} bytes of trailing data after the last chunk...",
+                png.trailing_data().len()
+            ))
+            .italic()
+            .yellow()
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//! Machine-readable records printed with `--format json` or `--format ndjson`.
+//! The field names and their meaning are part of the documented schema in the README,
+//! so only add new fields here and never rename or remove existing ones.
+
+use std::io::{self, Write};
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+
+use pngwhisper::png::{chunk::Chunk, ihdr::Ihdr, payload::Payload};
+
+use crate::args::OutputFormat;
+
+/// Version of the JSON schema, bumped on breaking changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Bytes as UTF-8 text if they are valid UTF-8 without control characters other
+/// than whitespace, base64 otherwise.
+#[derive(Debug, Serialize)]
+#[serde(tag = "encoding", content = "value", rename_all = "lowercase")]
+pub enum Data {
+    Utf8(String),
+    Base64(String),
+}
+
+impl From<&[u8]> for Data {
+    fn from(bytes: &[u8]) -> Self {
+        match as_text(bytes) {
+            Some(text) => Data::Utf8(text.into()),
+            None => Data::Base64(STANDARD.encode(bytes)),
+        }
+    }
+}
+
+/// Returns `bytes` as a string if they are valid UTF-8 without control characters
+/// other than whitespace, which is how binary data is told apart from text.
+pub fn as_text(bytes: &[u8]) -> Option<&str> {
+    std::str::from_utf8(bytes).ok().filter(|text| {
+        !text
+            .chars()
+            .any(|c| c.is_control() && !c.is_ascii_whitespace())
+    })
+}
+
+/// Formats `bytes` as lines of 16 bytes each, starting with their offset
+/// and followed by the printable ASCII characters among them.
+pub fn hexdump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, line)| {
+            let hex = line
+                .iter()
+                .enumerate()
+                .map(|(j, byte)| match j {
+                    8 => format!("  {:02x}", byte),
+                    0 => format!("{:02x}", byte),
+                    _ => format!(" {:02x}", byte),
+                })
+                .collect::<String>();
+            let ascii = line
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7e => byte as char,
+                    _ => '.',
+                })
+                .collect::<String>();
+            format!("{:08x}  {:<48}  |{}|", i * 16, hex, ascii)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImageRecord {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    /// The colour type code from the PNG spec.
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl From<&Ihdr> for ImageRecord {
+    fn from(ihdr: &Ihdr) -> Self {
+        Self {
+            width: ihdr.width(),
+            height: ihdr.height(),
+            bit_depth: ihdr.bit_depth(),
+            color_type: ihdr.color_type() as u8,
+            interlaced: ihdr.interlaced(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PropertiesRecord {
+    critical: bool,
+    public: bool,
+    reserved_bit_valid: bool,
+    safe_to_copy: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChunkRecord {
+    index: usize,
+    /// Offset of the chunk's length field from the start of the file.
+    offset: u64,
+    #[serde(rename = "type")]
+    chunk_type: String,
+    properties: PropertiesRecord,
+    length: u32,
+    /// The CRC stored in the file.
+    crc: u32,
+    crc_valid: bool,
+    data: Data,
+}
+
+impl ChunkRecord {
+    pub fn new(index: usize, offset: u64, chunk: &Chunk) -> Self {
+        let chunk_type = chunk.chunk_type();
+        Self {
+            index,
+            offset,
+            chunk_type: chunk_type.to_string(),
+            properties: PropertiesRecord {
+                critical: chunk_type.is_critical(),
+                public: chunk_type.is_public(),
+                reserved_bit_valid: chunk_type.is_reserved_bit_valid(),
+                safe_to_copy: chunk_type.is_safe_to_copy(),
+            },
+            length: chunk.length(),
+            crc: chunk.crc(),
+            crc_valid: chunk.has_valid_crc(),
+            data: chunk.data().into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageRecord {
+    index: usize,
+    #[serde(rename = "type")]
+    content_type: &'static str,
+    /// The original file name, only for files.
+    name: Option<String>,
+    length: usize,
+    data: Data,
+}
+
+impl MessageRecord {
+    pub fn new(index: usize, payload: &Payload) -> Self {
+        let (content_type, name) = match payload {
+            Payload::Text(_) => ("text", None),
+            Payload::File { name, .. } => ("file", name.clone()),
+        };
+        Self {
+            index,
+            content_type,
+            name,
+            length: payload.data().len(),
+            data: payload.data().into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrintDocument {
+    version: u32,
+    /// `None` if the first chunk isn't a valid `IHDR`.
+    image: Option<ImageRecord>,
+    chunks: Vec<ChunkRecord>,
+    trailing_data: usize,
+}
+
+impl PrintDocument {
+    pub fn new(image: Option<ImageRecord>, chunks: Vec<ChunkRecord>, trailing_data: usize) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            image,
+            chunks,
+            trailing_data,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DecodeDocument {
+    version: u32,
+    messages: Vec<MessageRecord>,
+}
+
+/// Prints decoded payloads as a single JSON document, or one JSON object per line.
+pub fn print_messages(payloads: &[Payload], format: OutputFormat) -> Result<()> {
+    let messages = payloads
+        .iter()
+        .enumerate()
+        .map(|(i, payload)| MessageRecord::new(i, payload));
+    match format {
+        OutputFormat::Ndjson => {
+            for message in messages {
+                print_line(&message)?;
+            }
+        }
+        _ => print_line(&DecodeDocument {
+            version: SCHEMA_VERSION,
+            messages: messages.collect(),
+        })?,
+    }
+    Ok(())
+}
+
+/// Prints `value` as compact JSON on a line of its own.
+pub fn print_line<T: Serialize>(value: &T) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, value)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pngwhisper::png::{chunk_type::ChunkType, ihdr::ColorType};
+    use serde_json::json;
+    use std::str::FromStr;
+
+    fn to_json<T: Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_data() {
+        assert_eq!(
+            to_json(&Data::from(&b"hello\n"[..])),
+            json!({ "encoding": "utf8", "value": "hello\n" })
+        );
+        assert_eq!(
+            to_json(&Data::from(&b"a\0b"[..])),
+            json!({ "encoding": "base64", "value": "YQBi" })
+        );
+        assert_eq!(
+            to_json(&Data::from(&[0xff, 0xfe][..])),
+            json!({ "encoding": "base64", "value": "//4=" })
+        );
+    }
+
+    #[test]
+    fn test_as_text() {
+        assert_eq!(
+            as_text(b"tabs\tand\r\nnewlines"),
+            Some("tabs\tand\r\nnewlines")
+        );
+        assert_eq!(as_text("ünïcode".as_bytes()), Some("ünïcode"));
+        assert_eq!(as_text(b"bell\x07"), None);
+        assert_eq!(as_text(&[0xc3]), None);
+    }
+
+    #[test]
+    fn test_hexdump() {
+        assert_eq!(hexdump(b""), "");
+        let bytes = (0x41..0x41 + 20).collect::<Vec<u8>>();
+        assert_eq!(
+            hexdump(&bytes),
+            "00000000  41 42 43 44 45 46 47 48  49 4a 4b 4c 4d 4e 4f 50  |ABCDEFGHIJKLMNOP|\n\
+             00000010  51 52 53 54                                       |QRST|"
+        );
+        assert_eq!(
+            hexdump(&[0, 0x7f, 0x20, 0x7e]),
+            "00000000  00 7f 20 7e                                       |.. ~|"
+        );
+        assert!(hexdump(&[0; 40])
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with("00000020  00"));
+    }
+
+    #[test]
+    fn test_chunk_record() {
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"data".to_vec());
+        assert_eq!(
+            to_json(&ChunkRecord::new(2, 33, &chunk)),
+            json!({
+                "index": 2,
+                "offset": 33,
+                "type": "ruSt",
+                "properties": {
+                    "critical": false,
+                    "public": false,
+                    "reserved_bit_valid": true,
+                    "safe_to_copy": true,
+                },
+                "length": 4,
+                "crc": chunk.crc(),
+                "crc_valid": true,
+                "data": { "encoding": "utf8", "value": "data" },
+            })
+        );
+    }
+
+    #[test]
+    fn test_message_record() {
+        assert_eq!(
+            to_json(&MessageRecord::new(0, &Payload::Text("hi".into()))),
+            json!({
+                "index": 0,
+                "type": "text",
+                "name": null,
+                "length": 2,
+                "data": { "encoding": "utf8", "value": "hi" },
+            })
+        );
+        let file = Payload::File {
+            name: Some("key.bin".into()),
+            data: vec![0, 1],
+        };
+        assert_eq!(
+            to_json(&MessageRecord::new(1, &file)),
+            json!({
+                "index": 1,
+                "type": "file",
+                "name": "key.bin",
+                "length": 2,
+                "data": { "encoding": "base64", "value": "AAE=" },
+            })
+        );
+    }
+
+    #[test]
+    fn test_documents() {
+        let ihdr = Ihdr::new(4, 3, 8, ColorType::Rgba, 0, 0, true).unwrap();
+        assert_eq!(
+            to_json(&PrintDocument::new(Some((&ihdr).into()), vec![], 0)),
+            json!({
+                "version": SCHEMA_VERSION,
+                "image": {
+                    "width": 4,
+                    "height": 3,
+                    "bit_depth": 8,
+                    "color_type": 6,
+                    "interlaced": true,
+                },
+                "chunks": [],
+                "trailing_data": 0,
+            })
+        );
+        assert_eq!(
+            to_json(&PrintDocument::new(None, vec![], 5)),
+            json!({ "version": SCHEMA_VERSION, "image": null, "chunks": [], "trailing_data": 5 })
+        );
+        assert_eq!(
+            to_json(&DecodeDocument {
+                version: SCHEMA_VERSION,
+                messages: vec![],
+            }),
+            json!({ "version": SCHEMA_VERSION, "messages": [] })
+        );
+    }
+}
@@ -1,43 +1,180 @@
 pub mod chunk;
 pub mod chunk_type;
+pub mod compression;
+pub mod diff;
+pub mod envelope;
+pub mod fragment;
+pub mod ihdr;
+pub mod lsb;
+pub mod payload;
+pub mod pixels;
+pub mod recover;
+pub mod text;
+pub mod validate;
 
-use anyhow::{bail, Context, Result};
-use chunk::Chunk;
+use crate::error::{PngError, Result};
+use chunk::{Chunk, ReadOutcome};
 use chunk_type::ChunkType;
-use std::{fmt::Display, io::Read, path::Path};
+use ihdr::Ihdr;
+use pixels::Image;
+use std::{
+    fmt::Display,
+    io::{self, BufReader, Read, Write},
+    ops::{Bound, RangeBounds},
+    path::Path,
+    str::FromStr,
+};
+use text::TextChunk;
 
 /// A PNG container as described by the PNG spec. \
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
 #[derive(Debug, Clone)]
 pub struct Png {
     chunks: Vec<Chunk>,
+    /// Bytes following the last chunk, only kept when parsed in `ParseMode::Lenient`.
+    trailing: Vec<u8>,
 }
 
 impl TryFrom<&[u8]> for Png {
-    type Error = anyhow::Error;
+    type Error = PngError;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        if Png::STANDARD_HEADER != value.get(..8).context("Empty header")? {
-            bail!("Invalid header")
+        Png::from_reader(value)
+    }
+}
+
+/// How to deal with damaged PNG streams or ones that don't end cleanly with an `IEND` chunk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Report bad CRCs, truncated chunks, a missing `IEND` and data after `IEND` as errors.
+    #[default]
+    Strict,
+    /// Accept a missing `IEND` and chunks with bad CRCs, keeping bytes after `IEND` or
+    /// of a truncated chunk as trailing data so they can be written back unchanged.
+    /// Use `Chunk::has_valid_crc` to find the damaged chunks.
+    Lenient,
+}
+
+/// A lazy iterator over the chunks of a PNG stream, reading one `Chunk` at a time.
+/// Created by `Png::read_chunks`.
+#[derive(Debug)]
+pub struct ChunkReader<R> {
+    reader: R,
+    mode: ParseMode,
+    /// Offset of the next chunk from the start of the stream.
+    offset: u64,
+    trailing: Vec<u8>,
+    seen_iend: bool,
+    done: bool,
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
         }
 
-        let mut offset = 8usize;
-        let mut chunks = vec![];
+        let chunk = self.read_next().transpose();
+        if !matches!(chunk, Some(Ok(_))) {
+            self.done = true;
+        }
+        chunk
+    }
+}
 
-        while let Some(bytes) = value
-            .get(offset..offset + 4)
-            .map(|x| <[u8; 4]>::try_from(x).unwrap())
-        {
-            let chunk_data_len = u32::from_be_bytes(bytes);
-            let chunk_len = (chunk_data_len + 4 * 3) as usize;
-            match value.get(offset..offset + chunk_len) {
-                Some(chunk_bytes) => chunks.push(Chunk::try_from(chunk_bytes)?),
-                None => break,
+impl<R: Read> ChunkReader<R> {
+    fn read_next(&mut self) -> Result<Option<Chunk>> {
+        if self.seen_iend {
+            return match self.mode {
+                ParseMode::Strict => match io::copy(&mut self.reader, &mut io::sink())? {
+                    0 => Ok(None),
+                    len => Err(PngError::TrailingData {
+                        offset: self.offset,
+                        len,
+                    }),
+                },
+                ParseMode::Lenient => {
+                    self.reader.read_to_end(&mut self.trailing)?;
+                    Ok(None)
+                }
+            };
+        }
+
+        match (Chunk::read_next(&mut self.reader)?, self.mode) {
+            (ReadOutcome::Chunk(chunk), mode) => {
+                let chunk = match mode {
+                    ParseMode::Strict => chunk.check_crc()?,
+                    ParseMode::Lenient => chunk,
+                };
+                self.offset += chunk.length() as u64 + 12;
+                self.seen_iend = chunk.chunk_type() == &ChunkType::IEND;
+                Ok(Some(chunk))
+            }
+            (ReadOutcome::Eof, ParseMode::Strict) => Err(PngError::MissingIend),
+            (ReadOutcome::Truncated(_), ParseMode::Strict) => Err(PngError::TruncatedChunk {
+                offset: self.offset,
+            }),
+            (ReadOutcome::Eof, ParseMode::Lenient) => Ok(None),
+            (ReadOutcome::Truncated(bytes), ParseMode::Lenient) => {
+                self.trailing = bytes;
+                Ok(None)
             }
-            offset += chunk_len;
         }
+    }
+
+    /// Offset of the next chunk from the start of the stream.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Bytes found after `IEND` or belonging to a truncated chunk. Only collected
+    /// in `ParseMode::Lenient` once the iterator is exhausted.
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing
+    }
+}
+
+/// Where a new `Chunk` should be placed within a `Png`'s chunk list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkPosition {
+    /// Right before the `IEND` chunk, the only spec-legal place for trailing ancillary chunks.
+    #[default]
+    BeforeIend,
+    /// Right after the `IHDR` chunk.
+    AfterIhdr,
+    /// Right before the first `IDAT` chunk.
+    BeforeIdat,
+    /// At an explicit index in the chunk list.
+    Index(usize),
+}
+
+impl FromStr for ChunkPosition {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "before-iend" => Self::BeforeIend,
+            "after-ihdr" => Self::AfterIhdr,
+            "before-idat" => Self::BeforeIdat,
+            other => Self::Index(
+                other
+                    .parse()
+                    .map_err(|_| PngError::InvalidPosition(s.into()))?,
+            ),
+        })
+    }
+}
 
-        Ok(Self { chunks })
+impl Display for ChunkPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChunkPosition::BeforeIend => f.write_str("before-iend"),
+            ChunkPosition::AfterIhdr => f.write_str("after-ihdr"),
+            ChunkPosition::BeforeIdat => f.write_str("before-idat"),
+            ChunkPosition::Index(idx) => write!(f, "{}", idx),
+        }
     }
 }
 
@@ -54,25 +191,75 @@ impl Png {
 
     /// Creates a `Png` from a list of chunks using the correct header.
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
-        Self { chunks }
+        Self {
+            chunks,
+            trailing: vec![],
+        }
     }
 
     /// Creates a `Png` from a file path.
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Png> {
-        let mut file = fs_err::File::open(path.as_ref())?;
-        let mut png_data = vec![];
-        png_data.extend(Png::is_png(&mut file)?);
-        file.read_to_end(&mut png_data)?;
+        Png::from_file_with(path, ParseMode::Strict)
+    }
+
+    /// Creates a `Png` from a file path using the given `ParseMode`.
+    pub fn from_file_with<P: AsRef<Path>>(path: P, mode: ParseMode) -> Result<Png> {
+        Png::from_reader_with(BufReader::new(fs_err::File::open(path.as_ref())?), mode)
+    }
+
+    /// Creates a `Png` from a stream starting with the PNG signature.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Png> {
+        Png::from_reader_with(reader, ParseMode::Strict)
+    }
+
+    /// Creates a `Png` from a stream starting with the PNG signature using the given `ParseMode`.
+    pub fn from_reader_with<R: Read>(reader: R, mode: ParseMode) -> Result<Png> {
+        let mut reader = Png::read_chunks_with(reader, mode)?;
+        let chunks = reader.by_ref().collect::<Result<_>>()?;
+        Ok(Self {
+            chunks,
+            trailing: reader.trailing,
+        })
+    }
+
+    /// Checks the PNG signature of a stream and returns a lazy iterator over its chunks,
+    /// so that only one `Chunk` is held in memory at a time.
+    pub fn read_chunks<R: Read>(reader: R) -> Result<ChunkReader<R>> {
+        Png::read_chunks_with(reader, ParseMode::Strict)
+    }
 
-        Png::try_from(png_data.as_slice())
+    /// Same as `Png::read_chunks`, using the given `ParseMode`.
+    pub fn read_chunks_with<R: Read>(mut reader: R, mode: ParseMode) -> Result<ChunkReader<R>> {
+        Png::is_png(&mut reader)?;
+        Ok(ChunkReader {
+            reader,
+            mode,
+            offset: Png::STANDARD_HEADER.len() as u64,
+            trailing: vec![],
+            seen_iend: false,
+            done: false,
+        })
     }
 
-    pub fn is_png(file: &mut fs_err::File) -> Result<[u8; 8]> {
+    /// Same as `Png::read_chunks_with`, but reads from a file path.
+    pub fn open_chunks<P: AsRef<Path>>(
+        path: P,
+        mode: ParseMode,
+    ) -> Result<ChunkReader<BufReader<fs_err::File>>> {
+        Png::read_chunks_with(BufReader::new(fs_err::File::open(path.as_ref())?), mode)
+    }
+
+    pub fn is_png<R: Read>(reader: &mut R) -> Result<[u8; 8]> {
         let mut buf = [0u8; 8];
-        file.read_exact(&mut buf)?;
+        reader
+            .read_exact(&mut buf)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => PngError::InvalidSignature,
+                _ => err.into(),
+            })?;
 
         if buf != Png::STANDARD_HEADER {
-            bail!("Invalid PNG file")
+            return Err(PngError::InvalidSignature);
         }
 
         Ok(buf)
@@ -83,6 +270,38 @@ impl Png {
         self.chunks.push(chunk);
     }
 
+    /// Inserts a chunk into this `Png` file's `Chunk` list at the given `position`.
+    pub fn insert_chunk(&mut self, chunk: Chunk, position: ChunkPosition) -> Result<()> {
+        let idx = self.resolve_position(position)?;
+        self.chunks.insert(idx, chunk);
+        Ok(())
+    }
+
+    /// Resolves a `ChunkPosition` into an index of this `Png`'s `Chunk` list.
+    pub fn resolve_position(&self, position: ChunkPosition) -> Result<usize> {
+        let find = |chunk_type: &ChunkType| {
+            self.chunks
+                .iter()
+                .position(|chunk| chunk_type == chunk.chunk_type())
+                .ok_or(PngError::ChunkNotFound(*chunk_type))
+        };
+
+        match position {
+            ChunkPosition::BeforeIend => find(&ChunkType::IEND),
+            ChunkPosition::AfterIhdr => find(&ChunkType::IHDR).map(|idx| idx + 1),
+            ChunkPosition::BeforeIdat => find(&ChunkType::IDAT),
+            ChunkPosition::Index(idx) => {
+                if idx > self.chunks.len() {
+                    return Err(PngError::IndexOutOfBounds {
+                        index: idx,
+                        len: self.chunks.len(),
+                    });
+                }
+                Ok(idx)
+            }
+        }
+    }
+
     /// Searches for a `Chunk` with the specified `chunk_type` and removes the first
     /// matching `Chunk` from this `Png` list of chunks.
     pub fn remove_chunk(&mut self, chunk_type: &ChunkType) -> Result<Chunk> {
@@ -93,7 +312,167 @@ impl Png {
         {
             return Ok(self.chunks.remove(idx));
         }
-        bail!("'{}' not found", chunk_type)
+        Err(PngError::ChunkNotFound(*chunk_type))
+    }
+
+    /// Replaces the `Chunk` at `idx` with `chunk`, returning the old one.
+    pub fn replace_chunk(&mut self, idx: usize, chunk: Chunk) -> Result<Chunk> {
+        match self.chunks.get_mut(idx) {
+            Some(old) => Ok(std::mem::replace(old, chunk)),
+            None => Err(PngError::IndexOutOfBounds {
+                index: idx,
+                len: self.chunks.len(),
+            }),
+        }
+    }
+
+    /// Stores `chunk` in place of the first `Chunk` of the same type, removing any others
+    /// of that type, or inserts it at `position` if there is none. Returns its index.
+    pub fn upsert(&mut self, chunk: Chunk, position: ChunkPosition) -> Result<usize> {
+        let chunk_type = *chunk.chunk_type();
+        match self
+            .chunks
+            .iter()
+            .position(|other| other.chunk_type() == &chunk_type)
+        {
+            Some(idx) => {
+                self.replace_chunk(idx, chunk)?;
+                let rest = self.chunks.split_off(idx + 1);
+                self.chunks.extend(
+                    rest.into_iter()
+                        .filter(|other| other.chunk_type() != &chunk_type),
+                );
+                Ok(idx)
+            }
+            None => {
+                let idx = self.resolve_position(position)?;
+                self.chunks.insert(idx, chunk);
+                Ok(idx)
+            }
+        }
+    }
+
+    /// Removes the `Chunk` at `idx` from this `Png`'s list of chunks.
+    pub fn remove_at(&mut self, idx: usize) -> Result<Chunk> {
+        if idx >= self.chunks.len() {
+            return Err(PngError::IndexOutOfBounds {
+                index: idx,
+                len: self.chunks.len(),
+            });
+        }
+        Ok(self.chunks.remove(idx))
+    }
+
+    /// Removes the `Chunk`s within `range` from this `Png`'s list of chunks.
+    pub fn remove_range<R: RangeBounds<usize>>(&mut self, range: R) -> Result<Vec<Chunk>> {
+        let len = self.chunks.len();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.saturating_add(1),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => len,
+        };
+        if start > end {
+            return Err(PngError::ReversedRange { start, end });
+        }
+        if end > len {
+            return Err(PngError::IndexOutOfBounds {
+                index: end - 1,
+                len,
+            });
+        }
+        Ok(self.chunks.drain(start..end).collect())
+    }
+
+    /// Keeps only the `Chunk`s for which `keep` returns `true`, returning the removed ones.
+    pub fn retain<F: FnMut(&Chunk) -> bool>(&mut self, mut keep: F) -> Vec<Chunk> {
+        let (kept, removed) = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|chunk| keep(chunk));
+        self.chunks = kept;
+        removed
+    }
+
+    /// Removes the ancillary `Chunk`s for which `strip` returns `true`, returning them.
+    /// Critical chunks are always kept, since the image can't be displayed without them.
+    pub fn strip<F: FnMut(&Chunk) -> bool>(&mut self, mut strip: F) -> Vec<Chunk> {
+        self.retain(|chunk| chunk.chunk_type().is_critical() || !strip(chunk))
+    }
+
+    /// Inflates and unfilters the `IDAT` chunks of this `Png` into raw scanlines.
+    pub fn image(&self) -> Result<Image> {
+        Image::read_from(self)
+    }
+
+    /// Filters and deflates `image`, replacing the `IDAT` chunks of this `Png`.
+    /// The dimensions and pixel format of `image` must match the `IHDR` chunk.
+    pub fn set_image(&mut self, image: &Image) -> Result<()> {
+        if image.ihdr() != &self.ihdr()? {
+            return Err(PngError::InvalidImageData(
+                "the image doesn't match the 'IHDR' chunk".into(),
+            ));
+        }
+        image.write_to(self)
+    }
+
+    /// Parses all of the `tEXt`, `zTXt` and `iTXt` chunks in this `Png`, each on its
+    /// own so that a malformed one doesn't hide the others.
+    pub fn text_chunks(&self) -> Vec<Result<TextChunk>> {
+        self.chunks
+            .iter()
+            .filter(|chunk| TextChunk::is_text_chunk(chunk.chunk_type()))
+            .map(TextChunk::try_from)
+            .collect()
+    }
+
+    /// Stores `text` in place of the first text chunk with the same keyword, removing
+    /// any others. The chunk is inserted at `position` if there is no such keyword yet.
+    pub fn set_text(&mut self, text: &TextChunk, position: ChunkPosition) -> Result<()> {
+        let chunk = text.to_chunk()?;
+        let matching = self.text_positions(text.keyword());
+        match matching.first() {
+            Some(&idx) => {
+                self.chunks[idx] = chunk;
+                for &idx in matching[1..].iter().rev() {
+                    self.chunks.remove(idx);
+                }
+                Ok(())
+            }
+            None => self.insert_chunk(chunk, position),
+        }
+    }
+
+    /// Removes all of the text chunks with the given `keyword`, returning them.
+    pub fn remove_text(&mut self, keyword: &str) -> Result<Vec<TextChunk>> {
+        let matching = self.text_positions(keyword);
+        if matching.is_empty() {
+            return Err(PngError::TextNotFound(keyword.into()));
+        }
+
+        let mut removed: Vec<TextChunk> = matching
+            .into_iter()
+            .rev()
+            .map(|idx| TextChunk::try_from(&self.chunks.remove(idx)))
+            .collect::<Result<_>>()?;
+        removed.reverse();
+        Ok(removed)
+    }
+
+    /// Indices of the well-formed text chunks with the given `keyword`.
+    fn text_positions(&self, keyword: &str) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| TextChunk::is_text_chunk(chunk.chunk_type()))
+            .filter(|(_, chunk)| {
+                TextChunk::try_from(*chunk).is_ok_and(|text| text.keyword() == keyword)
+            })
+            .map(|(idx, _)| idx)
+            .collect()
     }
 
     /// The header of this PNG.
@@ -101,6 +480,25 @@ impl Png {
         &Png::STANDARD_HEADER
     }
 
+    /// Parses the `IHDR` chunk, which must be the first chunk of a PNG.
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        match self.chunks.first() {
+            Some(chunk) if chunk.chunk_type() == &ChunkType::IHDR => Ihdr::try_from(chunk),
+            _ => Err(PngError::ChunkNotFound(ChunkType::IHDR)),
+        }
+    }
+
+    /// Bytes following the last chunk, kept when parsed in `ParseMode::Lenient`
+    /// and written back unchanged by `Png::write_to`.
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing
+    }
+
+    /// Removes the bytes following the last chunk, returning them.
+    pub fn take_trailing_data(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.trailing)
+    }
+
     /// Lists the `Chunk`s stored in this `Png`
     pub fn chunks(&self) -> &[Chunk] {
         &self.chunks
@@ -121,10 +519,31 @@ impl Png {
     /// Returns this `Png` as a byte sequence.
     /// These bytes will contain the header followed by the bytes of all of the chunks.
     pub fn as_bytes(&self) -> Vec<u8> {
-        Png::STANDARD_HEADER
-            .into_iter()
-            .chain(self.chunks.iter().flat_map(|chunk| chunk.as_bytes()))
-            .collect()
+        let mut buf = Vec::with_capacity(self.byte_len());
+        self.write_to(&mut buf)
+            .expect("writing to a Vec can't fail");
+        buf
+    }
+
+    /// The number of bytes `Png::as_bytes` returns, without serializing anything.
+    pub fn byte_len(&self) -> usize {
+        Png::STANDARD_HEADER.len()
+            + self
+                .chunks
+                .iter()
+                .map(|chunk| chunk.length() as usize + 12)
+                .sum::<usize>()
+            + self.trailing.len()
+    }
+
+    /// Writes this `Png` to `writer` in the same layout as `Png::as_bytes`,
+    /// one chunk at a time.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        for chunk in &self.chunks {
+            chunk.write_to(writer)?;
+        }
+        writer.write_all(&self.trailing)
     }
 }
 
@@ -135,13 +554,11 @@ mod tests {
     use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -168,6 +585,7 @@ mod tests {
     fn test_valid_from_bytes() {
         let chunk_bytes: Vec<u8> = testing_chunks()
             .into_iter()
+            .chain([Chunk::new(ChunkType::IEND, vec![])])
             .flat_map(|chunk| chunk.as_bytes())
             .collect();
 
@@ -247,6 +665,173 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let len = png.chunks().len();
+
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "Message").unwrap(),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+        assert_eq!(&png.chunks()[len - 1].chunk_type().to_string(), "TeSt");
+        assert_eq!(png.chunks()[len].chunk_type(), &ChunkType::IEND);
+
+        png.insert_chunk(
+            chunk_from_strings("AfTr", "Message").unwrap(),
+            ChunkPosition::AfterIhdr,
+        )
+        .unwrap();
+        assert_eq!(&png.chunks()[1].chunk_type().to_string(), "AfTr");
+
+        png.insert_chunk(
+            chunk_from_strings("BeFr", "Message").unwrap(),
+            ChunkPosition::BeforeIdat,
+        )
+        .unwrap();
+        let idx = png.resolve_position(ChunkPosition::BeforeIdat).unwrap();
+        assert_eq!(&png.chunks()[idx - 1].chunk_type().to_string(), "BeFr");
+
+        png.insert_chunk(
+            chunk_from_strings("FiRs", "Message").unwrap(),
+            ChunkPosition::Index(0),
+        )
+        .unwrap();
+        assert_eq!(&png.chunks()[0].chunk_type().to_string(), "FiRs");
+    }
+
+    #[test]
+    fn test_insert_chunk_invalid_position() {
+        let mut png = testing_png();
+        assert!(png
+            .insert_chunk(
+                chunk_from_strings("TeSt", "Message").unwrap(),
+                ChunkPosition::BeforeIend,
+            )
+            .is_err());
+        assert!(png
+            .insert_chunk(
+                chunk_from_strings("TeSt", "Message").unwrap(),
+                ChunkPosition::Index(4),
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_chunk_position_from_str() {
+        assert_eq!(
+            ChunkPosition::from_str("before-iend").unwrap(),
+            ChunkPosition::BeforeIend
+        );
+        assert_eq!(
+            ChunkPosition::from_str("After-IHDR").unwrap(),
+            ChunkPosition::AfterIhdr
+        );
+        assert_eq!(
+            ChunkPosition::from_str("3").unwrap(),
+            ChunkPosition::Index(3)
+        );
+        assert!(ChunkPosition::from_str("middle").is_err());
+    }
+
+    #[test]
+    fn test_ihdr() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!(ihdr.as_bytes(), png.chunks()[0].data());
+
+        let png = Png::from_chunks(testing_chunks());
+        assert!(matches!(
+            png.ihdr(),
+            Err(PngError::ChunkNotFound(chunk_type)) if chunk_type == ChunkType::IHDR
+        ));
+    }
+
+    #[test]
+    fn test_image() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut image = png.image().unwrap();
+        assert_eq!(
+            image.data().len(),
+            Image::stride(image.ihdr()) * image.ihdr().height() as usize
+        );
+
+        image.data_mut()[0] ^= 0xff;
+        png.set_image(&image).unwrap();
+        let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(png.image().unwrap(), image);
+
+        let mut png = Png::from_chunks(testing_chunks());
+        assert!(png.image().is_err());
+        assert!(png.set_image(&image).is_err());
+    }
+
+    #[test]
+    fn test_text_chunks() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let len = png.chunks().len();
+        let title = TextChunk::new("Title", "First").unwrap();
+        png.set_text(&title, ChunkPosition::AfterIhdr).unwrap();
+        png.set_text(
+            &TextChunk::new("Comment", "Hidden in plain sight").unwrap(),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+        png.insert_chunk(
+            TextChunk::new("Title", "Second")
+                .unwrap()
+                .to_chunk()
+                .unwrap(),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+        assert_eq!(png.text_chunks().len(), 3);
+
+        let title = title.with_compression(true);
+        png.set_text(&title, ChunkPosition::BeforeIend).unwrap();
+        assert_eq!(png.chunks().len(), len + 2);
+        assert_eq!(png.chunks()[1].chunk_type(), &ChunkType::ZTXT);
+        assert_eq!(png.text_chunks()[0].as_ref().unwrap(), &title);
+
+        let mut png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(png.remove_text("Title").unwrap(), vec![title]);
+        assert!(matches!(
+            png.remove_text("Title"),
+            Err(PngError::TextNotFound(_))
+        ));
+        assert_eq!(png.text_chunks().len(), 1);
+
+        png.insert_chunk(
+            Chunk::new(ChunkType::TEXT, b"No separator".to_vec()),
+            ChunkPosition::AfterIhdr,
+        )
+        .unwrap();
+        let texts = png.text_chunks();
+        assert_eq!(texts.len(), 2);
+        assert!(texts[0].is_err());
+        assert_eq!(texts[1].as_ref().unwrap().keyword(), "Comment");
+    }
+
+    #[test]
+    fn test_typed_errors() {
+        assert!(matches!(
+            Png::try_from(&PNG_FILE[..4]),
+            Err(PngError::InvalidSignature)
+        ));
+
+        let mut png = testing_png();
+        let kind = ChunkType::from_str("TeSt").unwrap();
+        assert!(matches!(
+            png.remove_chunk(&kind),
+            Err(PngError::ChunkNotFound(k)) if k == kind
+        ));
+        assert!(matches!(
+            png.resolve_position(ChunkPosition::Index(7)),
+            Err(PngError::IndexOutOfBounds { index: 7, len: 3 })
+        ));
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -257,12 +842,125 @@ mod tests {
         assert!(chunks.is_empty());
     }
 
+    #[test]
+    fn test_remove_at() {
+        let mut png = testing_png();
+        assert_eq!(png.remove_at(1).unwrap().chunk_type().to_string(), "miDl");
+        assert_eq!(png.chunks().len(), 2);
+        assert!(matches!(
+            png.remove_at(2),
+            Err(PngError::IndexOutOfBounds { index: 2, len: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_remove_range() {
+        let mut png = testing_png();
+        assert!(png.remove_range(1..4).is_err());
+        assert!(png.remove_range(2..=3).is_err());
+        let (start, end) = (2, 1);
+        assert!(matches!(
+            png.remove_range(start..end),
+            Err(PngError::ReversedRange { start: 2, end: 1 })
+        ));
+        assert_eq!(png.chunks().len(), 3);
+
+        let removed = png.remove_range(1..).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(png.chunks().len(), 1);
+        assert!(png.remove_range(..0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_replace_chunk() {
+        let mut png = testing_png();
+        let old = png
+            .replace_chunk(1, chunk_from_strings("TeSt", "Message").unwrap())
+            .unwrap();
+        assert_eq!(old.chunk_type().to_string(), "miDl");
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "TeSt");
+        assert!(png
+            .replace_chunk(3, chunk_from_strings("TeSt", "Message").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_upsert() {
+        let mut png = testing_png();
+        let chunk = |data| chunk_from_strings("TeSt", data).unwrap();
+        assert_eq!(png.upsert(chunk("1"), ChunkPosition::Index(1)).unwrap(), 1);
+        png.append_chunk(chunk("2"));
+        assert_eq!(png.chunks().len(), 5);
+
+        assert_eq!(png.upsert(chunk("3"), ChunkPosition::Index(0)).unwrap(), 1);
+        let kinds = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, ["FrSt", "TeSt", "miDl", "LASt"]);
+        assert_eq!(png.chunks()[1].data(), b"3");
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut png = testing_png();
+        let removed = png.retain(|chunk| chunk.chunk_type().is_public());
+        assert_eq!(removed.len(), 2);
+        assert_eq!(png.chunks().len(), 1);
+        assert_eq!(png.chunks()[0].chunk_type().to_string(), "LASt");
+    }
+
+    #[test]
+    fn test_byte_len() {
+        let mut png = testing_png();
+        assert_eq!(png.byte_len(), png.as_bytes().len());
+        png.trailing = b"trailing".to_vec();
+        assert_eq!(png.byte_len(), png.as_bytes().len());
+    }
+
+    #[test]
+    fn test_strip() {
+        let mut png = testing_png();
+        let removed = png.strip(|_| true);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].chunk_type().to_string(), "miDl");
+        assert_eq!(png.chunks().len(), 2);
+        assert!(png.strip(|_| true).is_empty());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_read_chunks() {
+        let mut chunks = Png::read_chunks(&PNG_FILE[..]).unwrap();
+        let first = chunks.next().unwrap().unwrap();
+        assert_eq!(first.chunk_type(), &ChunkType::IHDR);
+
+        let rest = chunks.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(rest.last().unwrap().chunk_type(), &ChunkType::IEND);
+        assert_eq!(
+            rest.len() + 1,
+            Png::try_from(&PNG_FILE[..]).unwrap().chunks().len()
+        );
+    }
+
+    #[test]
+    fn test_read_chunks_stops_on_error() {
+        let mut bytes = PNG_FILE.to_vec();
+        // Corrupt the CRC of the IHDR chunk
+        bytes[29] ^= 1;
+        let mut chunks = Png::read_chunks(bytes.as_slice()).unwrap();
+        assert!(chunks.next().unwrap().is_err());
+        assert!(chunks.next().is_none());
+
+        assert!(Png::read_chunks(&PNG_FILE[1..]).is_err());
+    }
+
     #[test]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
@@ -271,10 +969,85 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_truncated_file() {
+        let truncated = &PNG_FILE[..PNG_FILE.len() - 6];
+        assert!(matches!(
+            Png::try_from(truncated),
+            Err(PngError::TruncatedChunk { offset }) if offset == PNG_FILE.len() as u64 - 12
+        ));
+
+        let png = Png::from_reader_with(truncated, ParseMode::Lenient).unwrap();
+        assert_eq!(
+            png.trailing_data(),
+            &PNG_FILE[PNG_FILE.len() - 12..PNG_FILE.len() - 6]
+        );
+        assert_eq!(png.as_bytes(), truncated);
+    }
+
+    #[test]
+    fn test_missing_iend() {
+        let without_iend = &PNG_FILE[..PNG_FILE.len() - 12];
+        assert!(matches!(
+            Png::try_from(without_iend),
+            Err(PngError::MissingIend)
+        ));
+
+        let png = Png::from_reader_with(without_iend, ParseMode::Lenient).unwrap();
+        assert!(png.trailing_data().is_empty());
+        assert_eq!(png.as_bytes(), without_iend);
+    }
+
+    #[test]
+    fn test_bad_crc() {
+        let mut bytes = PNG_FILE.to_vec();
+        // The last byte of the 'IHDR' CRC
+        bytes[8 + 8 + 13 + 3] ^= 1;
+        assert!(matches!(
+            Png::try_from(bytes.as_ref()),
+            Err(PngError::CrcMismatch { chunk_type, .. }) if chunk_type == ChunkType::IHDR
+        ));
+
+        let mut png = Png::from_reader_with(bytes.as_slice(), ParseMode::Lenient).unwrap();
+        assert!(!png.chunks()[0].has_valid_crc());
+        assert_eq!(png.as_bytes(), bytes);
+        assert_eq!(png.fix_crcs(), vec![0]);
+        assert_eq!(png.as_bytes(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let bytes: Vec<u8> = PNG_FILE.iter().chain(b"trailing").copied().collect();
+        assert!(matches!(
+            Png::try_from(bytes.as_ref()),
+            Err(PngError::TrailingData { offset, len: 8 }) if offset == PNG_FILE.len() as u64
+        ));
+
+        let mut png = Png::from_reader_with(bytes.as_slice(), ParseMode::Lenient).unwrap();
+        assert_eq!(png.trailing_data(), b"trailing");
+        assert_eq!(png.as_bytes(), bytes);
+
+        png.insert_chunk(
+            chunk_from_strings("TeSt", "Message").unwrap(),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+        assert!(png.as_bytes().ends_with(b"IEND\xaeB`\x82trailing"));
+    }
+
+    #[test]
+    fn test_write_to() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut buf = vec![];
+        png.write_to(&mut buf).unwrap();
+        assert_eq!(buf, PNG_FILE.to_vec());
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
             .into_iter()
+            .chain([Chunk::new(ChunkType::IEND, vec![])])
             .flat_map(|chunk| chunk.as_bytes())
             .collect();
 
@@ -1,63 +1,41 @@
 use std::{
     borrow::Cow,
     fmt::{self, Display},
+    io::{self, Read, Write},
 };
 
-use anyhow::{bail, Context, Result};
-use itertools::Itertools;
-
-use crate::png::chunk_type::ChunkType;
+use crate::{
+    error::{PngError, Result},
+    png::chunk_type::ChunkType,
+};
 
 /// A validated PNG chunk. See the PNG Spec for more details. \
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html#Chunk-layout
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Chunk {
     length: u32,
     kind: ChunkType,
     data: Vec<u8>,
+    /// The stored CRC, which only differs from the computed one for damaged chunks
+    /// read in `ParseMode::Lenient`. \
     /// https://chrisballance.com/wp-content/uploads/2015/10/CRC-Primer.html
     crc: u32,
 }
 
-impl TryFrom<&[u8]> for Chunk {
-    type Error = anyhow::Error;
-
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        // Might be of interest
-        // https://docs.rs/byteorder/latest/byteorder/
-
-        let length = u32::from_be_bytes(
-            value
-                .get(..4)
-                .map(|x| <[u8; 4]>::try_from(x).unwrap())
-                .context("No length")?,
-        );
-        let kind = ChunkType::try_from(
-            value
-                .get(4..8)
-                .map(|x| <[u8; 4]>::try_from(x).unwrap())
-                .context("No chunk type")?,
-        )?;
-
-        if !kind.is_valid() {
-            bail!("Invalid chunk type")
-        }
-
-        let data = value.get(8..8 + length as usize).context("No chunk data")?;
-        let crc = u32::from_be_bytes(
-            value
-                .get(8 + length as usize..8 + length as usize + 4)
-                .map(|x| <[u8; 4]>::try_from(x).unwrap())
-                .context("No crc")?,
-        );
-
-        let chunk = Chunk::new(kind, data.into());
+/// The result of trying to read a single chunk from a stream.
+pub(crate) enum ReadOutcome {
+    Chunk(Chunk),
+    /// The stream ended cleanly before the chunk.
+    Eof,
+    /// The stream ended in the middle of the chunk, holding the bytes read so far.
+    Truncated(Vec<u8>),
+}
 
-        if crc != chunk.crc() {
-            bail!("Incorrect crc")
-        }
+impl TryFrom<&[u8]> for Chunk {
+    type Error = PngError;
 
-        Ok(chunk)
+    fn try_from(mut value: &[u8]) -> Result<Self, Self::Error> {
+        Chunk::read_from(&mut value)?.ok_or(PngError::TruncatedChunk { offset: 0 })
     }
 }
 
@@ -75,15 +53,13 @@ impl Display for Chunk {
 
 #[allow(dead_code)]
 impl Chunk {
+    /// The largest length the data of a chunk may have according to the PNG spec.
+    pub const MAX_LENGTH: usize = (1 << 31) - 1;
+
+    const CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        let crc = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(
-            &chunk_type
-                .bytes()
-                .iter()
-                .chain(data.iter())
-                .copied()
-                .collect::<Vec<_>>(),
-        );
+        let crc = Chunk::compute_crc(&chunk_type, &data);
         Self {
             length: data.len() as _,
             kind: chunk_type,
@@ -92,6 +68,84 @@ impl Chunk {
         }
     }
 
+    fn compute_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+        let mut digest = Chunk::CRC.digest();
+        digest.update(&chunk_type.bytes());
+        digest.update(data);
+        digest.finalize()
+    }
+
+    /// Reads and validates the next chunk from `reader`. Returns `None` once the
+    /// reader is exhausted. A reader ending in the middle of a chunk is reported as
+    /// `PngError::TruncatedChunk`, with an offset relative to where reading started.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Option<Chunk>> {
+        match Chunk::read_next(reader)? {
+            ReadOutcome::Chunk(chunk) => chunk.check_crc().map(Some),
+            ReadOutcome::Eof => Ok(None),
+            ReadOutcome::Truncated(_) => Err(PngError::TruncatedChunk { offset: 0 }),
+        }
+    }
+
+    /// Same as `Chunk::read_from`, but hands back the bytes of a truncated chunk
+    /// and keeps the stored CRC without checking it.
+    pub(crate) fn read_next<R: Read>(reader: &mut R) -> Result<ReadOutcome> {
+        let mut read_exact = |buf: &mut Vec<u8>, len: usize| -> Result<bool> {
+            let start = buf.len();
+            reader.take(len as u64).read_to_end(buf)?;
+            Ok(buf.len() - start == len)
+        };
+
+        let mut header = vec![];
+        if !read_exact(&mut header, 8)? {
+            return Ok(match header.is_empty() {
+                true => ReadOutcome::Eof,
+                false => ReadOutcome::Truncated(header),
+            });
+        }
+        let length = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let kind = ChunkType::try_from(<[u8; 4]>::try_from(&header[4..]).unwrap())?;
+
+        if !kind.is_valid() {
+            return Err(PngError::InvalidChunkType {
+                chunk_type: kind.to_string(),
+                reason: "reserved bit is set",
+            });
+        }
+        if length as usize > Chunk::MAX_LENGTH {
+            return Err(PngError::ChunkTooLong {
+                chunk_type: kind,
+                length,
+            });
+        }
+
+        let mut data = vec![];
+        let mut crc = vec![];
+        if !read_exact(&mut data, length as usize)? || !read_exact(&mut crc, 4)? {
+            return Ok(ReadOutcome::Truncated(
+                header.into_iter().chain(data).chain(crc).collect(),
+            ));
+        }
+
+        Ok(ReadOutcome::Chunk(Chunk {
+            length,
+            kind,
+            data,
+            crc: u32::from_be_bytes(crc.try_into().unwrap()),
+        }))
+    }
+
+    /// Returns this chunk if its stored CRC matches its type and data.
+    pub(crate) fn check_crc(self) -> Result<Chunk> {
+        match self.has_valid_crc() {
+            true => Ok(self),
+            false => Err(PngError::CrcMismatch {
+                chunk_type: self.kind,
+                expected: self.computed_crc(),
+                actual: self.crc,
+            }),
+        }
+    }
+
     /// The length of the data portion of this chunk.
     pub fn length(&self) -> u32 {
         self.length
@@ -107,17 +161,34 @@ impl Chunk {
         &self.data
     }
 
-    /// The CRC of this chunk.
+    /// The CRC of this chunk, as stored in the file it was read from.
     pub fn crc(&self) -> u32 {
         self.crc
     }
 
+    /// The CRC of this chunk's type and data.
+    pub fn computed_crc(&self) -> u32 {
+        Chunk::compute_crc(&self.kind, &self.data)
+    }
+
+    /// Whether the stored CRC matches, which is always the case unless the chunk
+    /// was read in `ParseMode::Lenient`.
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == self.computed_crc()
+    }
+
+    /// Replaces a mismatched stored CRC with the computed one, returning whether it changed.
+    pub fn fix_crc(&mut self) -> bool {
+        let crc = self.computed_crc();
+        std::mem::replace(&mut self.crc, crc) != crc
+    }
+
     /// Returns the data stored in this chunk as a `String`. This function will return an error
     /// if the stored data is not valid UTF-8.
     pub fn data_as_string(&self) -> Result<String> {
         std::str::from_utf8(&self.data)
             .map(|s| s.to_owned())
-            .map_err(anyhow::Error::msg)
+            .map_err(PngError::from)
     }
 
     pub fn data_as_lossy_string(&self) -> Cow<'_, str> {
@@ -131,13 +202,19 @@ impl Chunk {
     /// 3. The data itself *(`length` bytes)*
     /// 4. The CRC of the chunk type and data *(4 bytes)*
     pub fn as_bytes(&self) -> Vec<u8> {
-        self.length()
-            .to_be_bytes()
-            .into_iter()
-            .chain(self.chunk_type().bytes().into_iter())
-            .chain(self.data().iter().copied())
-            .chain(self.crc().to_be_bytes().into_iter())
-            .collect_vec()
+        let mut buf = Vec::with_capacity(self.data.len() + 12);
+        self.write_to(&mut buf)
+            .expect("writing to a Vec can't fail");
+        buf
+    }
+
+    /// Writes this chunk to `writer` in the same layout as `Chunk::as_bytes`,
+    /// without building an intermediate buffer.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.length().to_be_bytes())?;
+        writer.write_all(&self.chunk_type().bytes())?;
+        writer.write_all(self.data())?;
+        writer.write_all(&self.crc().to_be_bytes())
     }
 }
 
@@ -246,7 +323,36 @@ mod tests {
 
         let chunk = Chunk::try_from(chunk_data.as_ref());
 
-        assert!(chunk.is_err());
+        assert!(matches!(
+            chunk,
+            Err(PngError::CrcMismatch {
+                expected: 2882656334,
+                actual: 2882656333,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_chunk_too_long() {
+        let bytes: Vec<u8> = u32::MAX.to_be_bytes().into_iter().chain(*b"RuSt").collect();
+
+        assert!(matches!(
+            Chunk::read_from(&mut bytes.as_slice()),
+            Err(PngError::ChunkTooLong {
+                length: u32::MAX,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_chunk_write_to() {
+        let chunk = testing_chunk();
+        let mut buf = vec![];
+        chunk.write_to(&mut buf).unwrap();
+        assert_eq!(buf.len(), 42 + 12);
+        assert_eq!(Chunk::try_from(buf.as_ref()).unwrap().crc(), chunk.crc());
     }
 
     #[test]
@@ -3,7 +3,7 @@ use std::{
     str::FromStr,
 };
 
-use anyhow::{bail, Context};
+use crate::error::PngError;
 
 /// A validated PNG chunk type. See the PNG spec for more details. \
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html#Chunk-naming-conventions
@@ -13,19 +13,22 @@ pub struct ChunkType {
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = anyhow::Error;
+    type Error = PngError;
 
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
         if value.iter().all(|byte| byte.is_ascii_alphabetic()) {
             Ok(Self { buf: value })
         } else {
-            bail!("Invalid because of non-alphabetic chars")
+            Err(PngError::InvalidChunkType {
+                chunk_type: String::from_utf8_lossy(&value).into(),
+                reason: "non-alphabetic chars",
+            })
         }
     }
 }
 
 impl FromStr for ChunkType {
-    type Err = anyhow::Error;
+    type Err = PngError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.chars().all(|c| c.is_ascii_alphabetic()) {
@@ -33,10 +36,16 @@ impl FromStr for ChunkType {
                 buf: s
                     .as_bytes()
                     .try_into()
-                    .context("Invalid chunk type, it must be of 4 bytes")?,
+                    .map_err(|_| PngError::InvalidChunkType {
+                        chunk_type: s.into(),
+                        reason: "it must be of 4 bytes",
+                    })?,
             })
         } else {
-            bail!("Invalid because of non-alphabetic chars")
+            Err(PngError::InvalidChunkType {
+                chunk_type: s.into(),
+                reason: "non-alphabetic chars",
+            })
         }
     }
 }
@@ -49,6 +58,25 @@ impl Display for ChunkType {
 
 #[allow(dead_code)]
 impl ChunkType {
+    /// Image header, must appear first.
+    pub const IHDR: ChunkType = ChunkType { buf: *b"IHDR" };
+    /// Palette, required for indexed images and must come before `IDAT`.
+    pub const PLTE: ChunkType = ChunkType { buf: *b"PLTE" };
+    /// Image data, may be split across multiple consecutive chunks.
+    pub const IDAT: ChunkType = ChunkType { buf: *b"IDAT" };
+    /// Image trailer, must appear last.
+    pub const IEND: ChunkType = ChunkType { buf: *b"IEND" };
+    /// Latin-1 text.
+    pub const TEXT: ChunkType = ChunkType { buf: *b"tEXt" };
+    /// Compressed Latin-1 text.
+    pub const ZTXT: ChunkType = ChunkType { buf: *b"zTXt" };
+    /// International UTF-8 text, optionally compressed.
+    pub const ITXT: ChunkType = ChunkType { buf: *b"iTXt" };
+    /// Exif metadata, such as the camera and the location a photo was taken at.
+    pub const EXIF: ChunkType = ChunkType { buf: *b"eXIf" };
+    /// Time of the last modification.
+    pub const TIME: ChunkType = ChunkType { buf: *b"tIME" };
+
     /// Returns the raw bytes contained in this chunk
     pub fn bytes(&self) -> [u8; 4] {
         self.buf
@@ -74,6 +102,15 @@ impl ChunkType {
         self.buf[3] >> 5 & 1 != 0
     }
 
+    /// Returns true if chunks of this type may tell who made the image, with what, where
+    /// or when. Safe-to-copy chunks like `eXIf` and the text chunks don't depend on the
+    /// image data, so they are metadata rather than something needed to display it.
+    /// Chunks that do, like `gAMA` or `iCCP`, are unsafe to copy, except for `tIME`.
+    /// Private chunks can hold anything.
+    pub fn is_identifying(&self) -> bool {
+        !self.is_critical() && (self.is_safe_to_copy() || !self.is_public() || *self == Self::TIME)
+    }
+
     /// Returns true if the reserved byte is valid and all four bytes are represented by the characters A-Z or a-z.
     /// Note that this chunk type should always be valid as it is validated during construction.
     pub fn is_valid(&self) -> bool {
@@ -159,6 +196,28 @@ mod tests {
         assert!(chunk.is_valid());
     }
 
+    #[test]
+    pub fn test_chunk_type_is_identifying() {
+        for kind in [
+            "eXIf", "tIME", "tEXt", "zTXt", "iTXt", "pHYs", "wsPr", "prIV", "uNKn",
+        ] {
+            assert!(
+                ChunkType::from_str(kind).unwrap().is_identifying(),
+                "{}",
+                kind
+            );
+        }
+        for kind in [
+            "IHDR", "gAMA", "sRGB", "iCCP", "tRNS", "bKGD", "cICP", "uNKN",
+        ] {
+            assert!(
+                !ChunkType::from_str(kind).unwrap().is_identifying(),
+                "{}",
+                kind
+            );
+        }
+    }
+
     #[test]
     pub fn test_invalid_chunk_is_valid() {
         let chunk = ChunkType::from_str("Rust").unwrap();
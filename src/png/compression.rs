@@ -0,0 +1,130 @@
+use std::{
+    fmt::{self, Display},
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use crate::error::{PngError, Result};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+
+/// Compression algorithms available for payloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// Raw DEFLATE stream, as described in RFC 1951.
+    Deflate,
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            _ => Err(PngError::UnknownCompression(value.to_string())),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "deflate" => Ok(Compression::Deflate),
+            _ => Err(PngError::UnknownCompression(s.into())),
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::None => "none",
+            Compression::Deflate => "deflate",
+        })
+    }
+}
+
+impl Compression {
+    /// Default upper bound for the size of decompressed data.
+    pub const DEFAULT_LIMIT: usize = 256 * 1024 * 1024;
+
+    /// The identifier of this algorithm as stored alongside compressed data.
+    /// Identifiers are never reused, `2` is reserved for zstd.
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.into()),
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(vec![], flate2::Compression::best());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+
+    /// Decompresses `data`, failing if the result would exceed `limit` bytes.
+    pub fn decompress(&self, data: &[u8], limit: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![];
+        match self {
+            Compression::None => buf.extend_from_slice(data),
+            Compression::Deflate => {
+                DeflateDecoder::new(data)
+                    .take(limit as u64 + 1)
+                    .read_to_end(&mut buf)?;
+            }
+        }
+
+        if buf.len() > limit {
+            return Err(PngError::DecompressionLimit(limit));
+        }
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deflate_roundtrip() {
+        let data = "This is where your secret message will be! ".repeat(100);
+        let compressed = Compression::Deflate.compress(data.as_bytes()).unwrap();
+        assert!(compressed.len() < data.len());
+
+        let decompressed = Compression::Deflate
+            .decompress(&compressed, Compression::DEFAULT_LIMIT)
+            .unwrap();
+        assert_eq!(decompressed, data.as_bytes());
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        let compressed = Compression::Deflate.compress(&[0u8; 4096]).unwrap();
+        assert!(Compression::Deflate.decompress(&compressed, 4096).is_ok());
+        assert!(Compression::Deflate.decompress(&compressed, 4095).is_err());
+    }
+
+    #[test]
+    fn test_compression_ids() {
+        for compression in [Compression::None, Compression::Deflate] {
+            assert_eq!(
+                Compression::try_from(compression.id()).unwrap(),
+                compression
+            );
+            assert_eq!(
+                Compression::from_str(&compression.to_string()).unwrap(),
+                compression
+            );
+        }
+        assert!(Compression::try_from(2).is_err());
+        assert!(Compression::from_str("zip").is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::{chunk::Chunk, Png};
+
+/// Largest LCS table `Png::diff` builds, beyond which chunks are matched greedily.
+const MAX_LCS_CELLS: usize = 1 << 22;
+
+/// How a single chunk differs between two versions of a `Png`, as returned by `Png::diff`.
+/// Indices refer to the chunk lists of the old and the new version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkChange {
+    Unchanged {
+        old: usize,
+        new: usize,
+    },
+    Added {
+        new: usize,
+    },
+    Removed {
+        old: usize,
+    },
+    /// The same chunk at a different place relative to the unchanged ones.
+    Moved {
+        old: usize,
+        new: usize,
+    },
+}
+
+impl ChunkChange {
+    pub fn is_unchanged(&self) -> bool {
+        matches!(self, ChunkChange::Unchanged { .. })
+    }
+}
+
+impl Png {
+    /// Compares the chunks of this `Png` with those of `new`, in the order of `new` with
+    /// removed chunks placed where they used to be. Chunks are equal if their type, data
+    /// and CRC are.
+    pub fn diff(&self, new: &Png) -> Vec<ChunkChange> {
+        let (old, new) = (self.chunks(), new.chunks());
+        let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut changes: Vec<ChunkChange> = (0..prefix)
+            .map(|idx| ChunkChange::Unchanged { old: idx, new: idx })
+            .collect();
+        changes.extend(lcs_diff(
+            &old[prefix..old.len() - suffix],
+            &new[prefix..new.len() - suffix],
+            prefix,
+        ));
+        changes.extend((0..suffix).map(|i| ChunkChange::Unchanged {
+            old: old.len() - suffix + i,
+            new: new.len() - suffix + i,
+        }));
+
+        // A removed chunk that is added back elsewhere has moved, equal chunks share a CRC
+        let mut removed: HashMap<u32, Vec<usize>> = HashMap::new();
+        for change in &changes {
+            if let ChunkChange::Removed { old: idx } = *change {
+                removed.entry(old[idx].crc()).or_default().push(idx);
+            }
+        }
+        let mut paired = HashSet::new();
+        for change in &mut changes {
+            let ChunkChange::Added { new: added } = *change else {
+                continue;
+            };
+            let Some(candidates) = removed.get_mut(&new[added].crc()) else {
+                continue;
+            };
+            if let Some(pos) = candidates.iter().position(|&idx| old[idx] == new[added]) {
+                let idx = candidates.remove(pos);
+                paired.insert(idx);
+                *change = ChunkChange::Moved {
+                    old: idx,
+                    new: added,
+                };
+            }
+        }
+        changes.retain(
+            |change| !matches!(change, ChunkChange::Removed { old } if paired.contains(old)),
+        );
+        changes
+    }
+}
+
+/// Diffs two lists of chunks through their longest common subsequence, with
+/// indices shifted by `offset`.
+fn lcs_diff(old: &[Chunk], new: &[Chunk], offset: usize) -> Vec<ChunkChange> {
+    if (old.len() + 1).saturating_mul(new.len() + 1) > MAX_LCS_CELLS {
+        return greedy_diff(old, new, offset);
+    }
+
+    // lengths[i][j] is the length of the LCS of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match old[i] == new[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let mut changes = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            changes.push(ChunkChange::Unchanged {
+                old: offset + i,
+                new: offset + j,
+            });
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            changes.push(ChunkChange::Removed { old: offset + i });
+            i += 1;
+        } else {
+            changes.push(ChunkChange::Added { new: offset + j });
+            j += 1;
+        }
+    }
+    changes
+}
+
+/// Diffs two lists of chunks too long for `lcs_diff` by matching every new chunk with the
+/// next equal old one, which finds the same changes for chunks that were only added or
+/// removed, but may report more than needed for chunks that were reordered.
+fn greedy_diff(old: &[Chunk], new: &[Chunk], offset: usize) -> Vec<ChunkChange> {
+    let mut positions: HashMap<u32, VecDeque<usize>> = HashMap::new();
+    for (i, chunk) in old.iter().enumerate() {
+        positions.entry(chunk.crc()).or_default().push_back(i);
+    }
+
+    let mut changes = vec![];
+    let mut next = 0;
+    for (j, chunk) in new.iter().enumerate() {
+        let found = positions.get_mut(&chunk.crc()).and_then(|candidates| {
+            while candidates.front().is_some_and(|&i| i < next) {
+                candidates.pop_front();
+            }
+            let pos = candidates.iter().position(|&i| old[i] == *chunk)?;
+            candidates.remove(pos)
+        });
+        match found {
+            Some(i) => {
+                changes.extend((next..i).map(|i| ChunkChange::Removed { old: offset + i }));
+                changes.push(ChunkChange::Unchanged {
+                    old: offset + i,
+                    new: offset + j,
+                });
+                next = i + 1;
+            }
+            None => changes.push(ChunkChange::Added { new: offset + j }),
+        }
+    }
+    changes.extend((next..old.len()).map(|i| ChunkChange::Removed { old: offset + i }));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn png(chunks: &str) -> Png {
+        Png::from_chunks(
+            chunks
+                .split(' ')
+                .map(|kind| Chunk::new(ChunkType::from_str(kind).unwrap(), vec![]))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_diff_unchanged() {
+        let old = png("IHDR IDAT IEND");
+        assert!(old.diff(&old).iter().all(ChunkChange::is_unchanged));
+    }
+
+    #[test]
+    fn test_diff_added_removed() {
+        let old = png("IHDR tEXt IDAT IEND");
+        let new = png("IHDR IDAT wsPr IEND");
+        assert_eq!(
+            old.diff(&new),
+            [
+                ChunkChange::Unchanged { old: 0, new: 0 },
+                ChunkChange::Removed { old: 1 },
+                ChunkChange::Unchanged { old: 2, new: 1 },
+                ChunkChange::Added { new: 2 },
+                ChunkChange::Unchanged { old: 3, new: 3 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_moved() {
+        let old = png("IHDR tEXt IDAT IEND");
+        let new = png("IHDR IDAT tEXt IEND");
+        let changes = old.diff(&new);
+        assert_eq!(changes.len(), 4);
+        assert!(changes.contains(&ChunkChange::Moved { old: 1, new: 2 }));
+    }
+
+    #[test]
+    fn test_diff_many_chunks() {
+        let idat = |i: u32| Chunk::new(ChunkType::IDAT, i.to_be_bytes().to_vec());
+        let text = Chunk::new(ChunkType::TEXT, b"moved".to_vec());
+        let mut old_chunks = (0..3000).map(idat).collect::<Vec<_>>();
+        old_chunks[0] = Chunk::new(ChunkType::IHDR, vec![]);
+        old_chunks[1] = text.clone();
+        old_chunks[2999] = Chunk::new(ChunkType::IEND, vec![]);
+        let mut new_chunks = old_chunks.clone();
+        new_chunks.remove(1);
+        new_chunks.remove(100);
+        new_chunks.insert(2000, idat(10_000));
+        new_chunks.insert(2500, text);
+
+        let changes = Png::from_chunks(old_chunks).diff(&Png::from_chunks(new_chunks));
+        let changed = changes
+            .into_iter()
+            .filter(|change| !change.is_unchanged())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changed,
+            [
+                ChunkChange::Removed { old: 101 },
+                ChunkChange::Added { new: 2000 },
+                ChunkChange::Moved { old: 1, new: 2500 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+
+use crate::{
+    crypto,
+    error::{PngError, Result},
+    png::{
+        compression::Compression,
+        fragment::Fragment,
+        payload::{ContentType, Payload},
+    },
+};
+
+/// A versioned container wrapping a `Payload` before it is stored in a chunk,
+/// allowing pngwhisper messages to be told apart from other chunk data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    version: u8,
+    compressed: bool,
+    encrypted: bool,
+    content_type: ContentType,
+    body: Vec<u8>,
+}
+
+impl TryFrom<&[u8]> for Envelope {
+    type Error = PngError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if !Envelope::is_envelope(value) {
+            return Err(PngError::NotAnEnvelope);
+        }
+        let missing = |what: &str| PngError::InvalidEnvelope(format!("no {}", what));
+
+        let version = *value.get(4).ok_or_else(|| missing("version"))?;
+        if version != Envelope::VERSION {
+            return Err(PngError::UnsupportedVersion {
+                found: version,
+                supported: Envelope::VERSION,
+            });
+        }
+
+        let flags = *value.get(5).ok_or_else(|| missing("flags"))?;
+        if flags & !(Envelope::FLAG_COMPRESSED | Envelope::FLAG_ENCRYPTED) != 0 {
+            return Err(PngError::InvalidEnvelope(format!(
+                "unknown flags {:#010b}",
+                flags
+            )));
+        }
+        let content_type =
+            ContentType::try_from(*value.get(6).ok_or_else(|| missing("content type"))?)?;
+
+        let length = Envelope::body_len(value)?;
+        let checksum = u32::from_be_bytes(
+            value
+                .get(11..15)
+                .map(|x| <[u8; 4]>::try_from(x).unwrap())
+                .ok_or_else(|| missing("checksum"))?,
+        );
+
+        let body = &value[Envelope::HEADER_LEN..];
+        if body.len() != length {
+            return Err(PngError::InvalidEnvelope(format!(
+                "length mismatch, expected {} bytes but found {}",
+                length,
+                body.len()
+            )));
+        }
+        if checksum != Envelope::checksum(body) {
+            return Err(PngError::ChecksumMismatch {
+                expected: Envelope::checksum(body),
+                actual: checksum,
+            });
+        }
+
+        Ok(Self {
+            version,
+            compressed: flags & Envelope::FLAG_COMPRESSED != 0,
+            encrypted: flags & Envelope::FLAG_ENCRYPTED != 0,
+            content_type,
+            body: body.into(),
+        })
+    }
+}
+
+impl Envelope {
+    /// Identifies the start of a pngwhisper envelope.
+    pub const MAGIC: [u8; 4] = *b"PWsp";
+    /// The envelope format version written by this build.
+    pub const VERSION: u8 = 1;
+    /// Length of the envelope header preceding the body.
+    pub const HEADER_LEN: usize = 15;
+    /// The longest body the length field of the header can describe.
+    pub const MAX_BODY_LEN: usize = u32::MAX as usize;
+
+    const FLAG_COMPRESSED: u8 = 1 << 0;
+    const FLAG_ENCRYPTED: u8 = 1 << 1;
+
+    /// Wraps `payload` into an envelope, compressing it with `compression` and then
+    /// encrypting it if a `passphrase` is given.
+    pub fn seal(
+        payload: &Payload,
+        compression: Compression,
+        passphrase: Option<&[u8]>,
+    ) -> Result<Envelope> {
+        let body = payload.body()?;
+        let body = match compression {
+            Compression::None => body,
+            _ => [compression.id()]
+                .into_iter()
+                .chain(compression.compress(&body)?)
+                .collect(),
+        };
+        let len = body.len() + passphrase.map_or(0, |_| crypto::OVERHEAD);
+        if len > Envelope::MAX_BODY_LEN {
+            return Err(PngError::TooLarge("Payload"));
+        }
+
+        let mut envelope = Self {
+            version: Envelope::VERSION,
+            compressed: compression != Compression::None,
+            encrypted: passphrase.is_some(),
+            content_type: payload.content_type(),
+            body: vec![],
+        };
+        envelope.body = match passphrase {
+            Some(passphrase) => crypto::encrypt(passphrase, &body, &envelope.header(len))?,
+            None => body,
+        };
+        Ok(envelope)
+    }
+
+    /// Number of bytes an envelope adds on top of the payload body, not counting the
+    /// change in size caused by `compression` itself.
+    pub fn overhead(compression: Compression, encrypted: bool) -> usize {
+        Envelope::HEADER_LEN
+            + if compression == Compression::None {
+                0
+            } else {
+                1
+            }
+            + if encrypted { crypto::OVERHEAD } else { 0 }
+    }
+
+    /// Unwraps the `Payload` stored in this envelope. A `passphrase` is required
+    /// if the envelope is encrypted.
+    pub fn open(&self, passphrase: Option<&[u8]>) -> Result<Payload> {
+        self.open_with_limit(passphrase, Compression::DEFAULT_LIMIT)
+    }
+
+    /// Same as `Envelope::open`, but fails if the decompressed payload would
+    /// exceed `limit` bytes.
+    pub fn open_with_limit(&self, passphrase: Option<&[u8]>, limit: usize) -> Result<Payload> {
+        // The header is authenticated along with the body, but a cleared encryption
+        // flag can only be noticed if a passphrase was expected
+        let body = match (self.encrypted, passphrase) {
+            (true, Some(passphrase)) => {
+                crypto::decrypt(passphrase, &self.body, &self.header(self.body.len()))?
+            }
+            (true, None) => return Err(PngError::PassphraseRequired),
+            (false, Some(_)) => return Err(PngError::NotEncrypted),
+            (false, None) => self.body.clone(),
+        };
+        let body = if self.compressed {
+            let (&id, data) = body
+                .split_first()
+                .ok_or_else(|| PngError::InvalidEnvelope("no compression algorithm".into()))?;
+            Compression::try_from(id)?.decompress(data, limit)?
+        } else {
+            body
+        };
+        Payload::from_body(self.content_type, &body)
+    }
+
+    /// Reads all envelopes stored in a sequence of chunk data, reassembling the ones
+    /// split into fragments. Data that is neither an envelope nor a fragment is skipped.
+    pub fn extract<'a, I>(data: I) -> Result<Vec<Envelope>>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        enum Slot {
+            Whole(Envelope),
+            Fragmented(u32),
+        }
+
+        let mut slots = vec![];
+        let mut sequences: HashMap<u32, Vec<Fragment>> = HashMap::new();
+        for data in data {
+            if Envelope::is_envelope(data) {
+                slots.push(Slot::Whole(Envelope::try_from(data).map_err(|err| {
+                    PngError::Message {
+                        index: slots.len() + 1,
+                        source: err.into(),
+                    }
+                })?));
+            } else if Fragment::is_fragment(data) {
+                let fragment = Fragment::try_from(data)?;
+                let fragments = sequences.entry(fragment.id()).or_insert_with(|| {
+                    slots.push(Slot::Fragmented(fragment.id()));
+                    vec![]
+                });
+                fragments.push(fragment);
+            }
+        }
+
+        slots
+            .into_iter()
+            .enumerate()
+            .map(|(i, slot)| match slot {
+                Slot::Whole(envelope) => Ok(envelope),
+                Slot::Fragmented(id) => {
+                    Fragment::reassemble(sequences.remove(&id).unwrap_or_default())
+                        .and_then(|data| Envelope::try_from(data.as_slice()))
+                        .map_err(|err| PngError::Message {
+                            index: i + 1,
+                            source: err.into(),
+                        })
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the data for the chunks holding this envelope, each at most `max_len`
+    /// bytes long. The envelope is split into fragments only if it doesn't fit into one.
+    pub fn split(&self, max_len: usize) -> Result<Vec<Vec<u8>>> {
+        let bytes = self.as_bytes();
+        if bytes.len() <= max_len {
+            return Ok(vec![bytes]);
+        }
+        Ok(Fragment::split(&bytes, max_len)?
+            .iter()
+            .map(Fragment::as_bytes)
+            .collect())
+    }
+
+    /// Reads the length of the body from the `header` of an envelope, which is all
+    /// that's needed to know how many more bytes to read.
+    pub fn body_len(header: &[u8]) -> Result<usize> {
+        if !Envelope::is_envelope(header) {
+            return Err(PngError::NotAnEnvelope);
+        }
+        let length = header
+            .get(7..11)
+            .map(|x| <[u8; 4]>::try_from(x).unwrap())
+            .ok_or_else(|| PngError::InvalidEnvelope("no length".into()))?;
+        Ok(u32::from_be_bytes(length) as usize)
+    }
+
+    /// Returns true if `data` starts with the envelope magic bytes.
+    pub fn is_envelope(data: &[u8]) -> bool {
+        data.starts_with(&Envelope::MAGIC)
+    }
+
+    /// The format version of this envelope.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Whether the body of this envelope is compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Whether the body of this envelope is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// The `ContentType` of the wrapped payload.
+    pub fn content_type(&self) -> ContentType {
+        self.content_type
+    }
+
+    fn checksum(body: &[u8]) -> u32 {
+        crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(body)
+    }
+
+    /// Returns this envelope as a byte sequence.
+    /// The following data is included in this byte sequence in order:
+    /// 1. Magic bytes `PWsp` *(4 bytes)*
+    /// 2. Format version *(1 byte)*
+    /// 3. Flags, bit 0 for compression and bit 1 for encryption *(1 byte)*
+    /// 4. Content type *(1 byte)*
+    /// 5. Length of the body *(4 bytes)*
+    /// 6. CRC of the body *(4 bytes)*
+    /// 7. The body itself *(`length` bytes)*
+    ///
+    /// When compressed, the body, after decryption, starts with the id of the
+    /// compression algorithm *(1 byte)* followed by the compressed payload.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.header(self.body.len())
+            .into_iter()
+            .chain(Envelope::checksum(&self.body).to_be_bytes())
+            .chain(self.body.iter().copied())
+            .collect()
+    }
+
+    /// The header up to and including the length of a body of `len` bytes. It is the
+    /// associated data of encrypted bodies, so that changing it makes decryption fail.
+    fn header(&self, len: usize) -> Vec<u8> {
+        let mut flags = 0;
+        if self.compressed {
+            flags |= Envelope::FLAG_COMPRESSED;
+        }
+        if self.encrypted {
+            flags |= Envelope::FLAG_ENCRYPTED;
+        }
+
+        Envelope::MAGIC
+            .into_iter()
+            .chain([self.version, flags, self.content_type as u8])
+            .chain((len as u32).to_be_bytes())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_payload() -> Payload {
+        Payload::File {
+            name: Some("secret.txt".into()),
+            data: b"This is where your secret message will be!".to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let envelope = Envelope::seal(&testing_payload(), Compression::None, None).unwrap();
+        let bytes = envelope.as_bytes();
+        assert!(Envelope::is_envelope(&bytes));
+
+        let parsed = Envelope::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(parsed, envelope);
+        assert_eq!(parsed.version(), Envelope::VERSION);
+        assert_eq!(parsed.content_type(), ContentType::File);
+        assert!(!parsed.is_encrypted());
+        assert_eq!(parsed.open(None).unwrap(), testing_payload());
+    }
+
+    #[test]
+    fn test_envelope_overhead() {
+        let body_len = testing_payload().body().unwrap().len();
+        let envelope = Envelope::seal(&testing_payload(), Compression::None, None).unwrap();
+        assert_eq!(
+            envelope.as_bytes().len(),
+            body_len + Envelope::overhead(Compression::None, false)
+        );
+
+        let envelope =
+            Envelope::seal(&testing_payload(), Compression::None, Some(b"hunter2")).unwrap();
+        assert_eq!(
+            envelope.as_bytes().len(),
+            body_len + Envelope::overhead(Compression::None, true)
+        );
+        assert_eq!(
+            Envelope::overhead(Compression::Deflate, true),
+            Envelope::HEADER_LEN + 1 + crate::crypto::OVERHEAD
+        );
+    }
+
+    #[test]
+    fn test_encrypted_envelope() {
+        let envelope =
+            Envelope::seal(&testing_payload(), Compression::None, Some(b"hunter2")).unwrap();
+        let parsed = Envelope::try_from(envelope.as_bytes().as_ref()).unwrap();
+        assert!(parsed.is_encrypted());
+        assert!(parsed.open(None).is_err());
+        assert!(parsed.open(Some(b"hunter3")).is_err());
+        assert_eq!(parsed.open(Some(b"hunter2")).unwrap(), testing_payload());
+    }
+
+    #[test]
+    fn test_tampered_header() {
+        let payload = Payload::Text("This is where your secret message will be!".into());
+        let bytes = Envelope::seal(&payload, Compression::None, Some(b"hunter2"))
+            .unwrap()
+            .as_bytes();
+        let tamper = |offset: usize, bit: u8| {
+            let mut bytes = bytes.clone();
+            bytes[offset] ^= bit;
+            Envelope::try_from(bytes.as_ref()).unwrap()
+        };
+
+        let cleared = tamper(5, Envelope::FLAG_ENCRYPTED);
+        assert!(!cleared.is_encrypted());
+        assert!(matches!(
+            cleared.open(Some(b"hunter2")),
+            Err(PngError::NotEncrypted)
+        ));
+        assert!(matches!(
+            tamper(5, Envelope::FLAG_COMPRESSED).open(Some(b"hunter2")),
+            Err(PngError::Decryption)
+        ));
+        assert!(matches!(
+            tamper(6, 1).open(Some(b"hunter2")),
+            Err(PngError::Decryption)
+        ));
+    }
+
+    #[test]
+    fn test_compressed_envelope() {
+        let payload = Payload::Text("This is where your secret message will be! ".repeat(100));
+        let envelope = Envelope::seal(&payload, Compression::Deflate, Some(b"hunter2")).unwrap();
+        let parsed = Envelope::try_from(envelope.as_bytes().as_ref()).unwrap();
+        assert!(parsed.is_compressed());
+        assert!(parsed.is_encrypted());
+        assert!(parsed.as_bytes().len() < payload.data().len());
+        assert_eq!(parsed.open(Some(b"hunter2")).unwrap(), payload);
+        assert!(parsed
+            .open_with_limit(Some(b"hunter2"), payload.data().len() - 1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_extract_envelopes() {
+        let first = Envelope::seal(&testing_payload(), Compression::None, None).unwrap();
+        let second = Envelope::seal(&Payload::Text("Hi".into()), Compression::None, None).unwrap();
+
+        let mut data = first.split(Fragment::HEADER_LEN + 8).unwrap();
+        assert!(data.len() > 1);
+        data.insert(1, b"Unrelated chunk data".to_vec());
+        data.insert(2, second.split(usize::MAX).unwrap().remove(0));
+        data.swap(3, 4);
+
+        let envelopes = Envelope::extract(data.iter().map(Vec::as_slice)).unwrap();
+        assert_eq!(envelopes, vec![first, second]);
+
+        data.remove(0);
+        assert!(Envelope::extract(data.iter().map(Vec::as_slice)).is_err());
+    }
+
+    #[test]
+    fn test_invalid_envelope() {
+        let bytes = Envelope::seal(&testing_payload(), Compression::None, None)
+            .unwrap()
+            .as_bytes();
+
+        assert!(Envelope::try_from(&b"This is not an envelope"[..]).is_err());
+        assert_eq!(
+            Envelope::body_len(&bytes).unwrap(),
+            bytes.len() - Envelope::HEADER_LEN
+        );
+        assert!(Envelope::body_len(&bytes[..10]).is_err());
+
+        let mut bad_version = bytes.clone();
+        bad_version[4] = Envelope::VERSION + 1;
+        assert!(matches!(
+            Envelope::try_from(bad_version.as_ref()),
+            Err(PngError::UnsupportedVersion { found, .. }) if found == Envelope::VERSION + 1
+        ));
+
+        let mut bad_flags = bytes.clone();
+        bad_flags[5] = 0x80;
+        assert!(Envelope::try_from(bad_flags.as_ref()).is_err());
+
+        let mut bad_checksum = bytes.clone();
+        *bad_checksum.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            Envelope::try_from(bad_checksum.as_ref()),
+            Err(PngError::ChecksumMismatch { .. })
+        ));
+
+        assert!(Envelope::try_from(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use crate::error::{PngError, Result};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use itertools::Itertools;
+
+/// A sequence-numbered piece of a larger byte sequence, allowing data that doesn't
+/// fit into a single chunk to be spread over several.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    /// Shared by all fragments of the same sequence.
+    id: u32,
+    index: u32,
+    count: u32,
+    data: Vec<u8>,
+}
+
+impl TryFrom<&[u8]> for Fragment {
+    type Error = PngError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if !Fragment::is_fragment(value) {
+            return Err(PngError::InvalidFragment("missing magic bytes".into()));
+        }
+
+        let read_u32 = |offset: usize, what: &str| {
+            value
+                .get(offset..offset + 4)
+                .map(|x| u32::from_be_bytes(x.try_into().unwrap()))
+                .ok_or_else(|| PngError::InvalidFragment(format!("no {}", what)))
+        };
+        let id = read_u32(4, "id")?;
+        let index = read_u32(8, "index")?;
+        let count = read_u32(12, "count")?;
+        if index >= count {
+            return Err(PngError::InvalidFragment(format!(
+                "index {} is out of bounds for {} fragments",
+                index, count
+            )));
+        }
+
+        Ok(Self {
+            id,
+            index,
+            count,
+            data: value[Fragment::HEADER_LEN..].into(),
+        })
+    }
+}
+
+impl Fragment {
+    /// Identifies the start of a pngwhisper fragment.
+    pub const MAGIC: [u8; 4] = *b"PWfr";
+    /// Length of the fragment header preceding the data.
+    pub const HEADER_LEN: usize = 16;
+    /// How many missing indices are listed when reassembling fails.
+    pub const MAX_LISTED_MISSING: usize = 10;
+
+    /// Splits `data` into fragments whose serialized form is at most `max_len` bytes.
+    pub fn split(data: &[u8], max_len: usize) -> Result<Vec<Fragment>> {
+        if max_len <= Fragment::HEADER_LEN {
+            return Err(PngError::InvalidFragment(format!(
+                "fragments must be larger than their {} byte header",
+                Fragment::HEADER_LEN
+            )));
+        }
+
+        let pieces = data.chunks(max_len - Fragment::HEADER_LEN).collect_vec();
+        let count =
+            u32::try_from(pieces.len()).map_err(|_| PngError::TooLarge("Fragment count"))?;
+        let id = OsRng.next_u32();
+
+        Ok(pieces
+            .into_iter()
+            .enumerate()
+            .map(|(index, piece)| Self {
+                id,
+                index: index as u32,
+                count,
+                data: piece.into(),
+            })
+            .collect())
+    }
+
+    /// Joins the fragments of a single sequence back together, in any order.
+    /// Fails if fragments are missing, duplicated or belong to different sequences.
+    pub fn reassemble(mut fragments: Vec<Fragment>) -> Result<Vec<u8>> {
+        let first = fragments
+            .first()
+            .ok_or_else(|| PngError::InvalidFragment("no fragments to reassemble".into()))?;
+        let (id, count) = (first.id, first.count);
+        if let Some(other) = fragments.iter().find(|f| f.id != id || f.count != count) {
+            return Err(PngError::InvalidFragment(format!(
+                "fragment {} of {} doesn't belong to the sequence {:08x} of {} fragments",
+                other.index + 1,
+                other.count,
+                id,
+                count
+            )));
+        }
+
+        fragments.sort_by_key(|fragment| fragment.index);
+        if let Some((fragment, _)) = fragments
+            .iter()
+            .tuple_windows()
+            .find(|(a, b)| a.index == b.index)
+        {
+            return Err(PngError::DuplicateFragment {
+                index: fragment.index,
+                count,
+            });
+        }
+        // The count comes from the fragments themselves, so only the gaps between the
+        // fragments that are there are walked instead of every possible index
+        let present = fragments.len() as u32;
+        if present != count {
+            let missing = fragments
+                .iter()
+                .map(|f| f.index)
+                .chain([count])
+                .scan(0, |next, index| {
+                    let gap = *next..index;
+                    *next = index.saturating_add(1);
+                    Some(gap)
+                })
+                .flatten()
+                .take(Fragment::MAX_LISTED_MISSING)
+                .collect_vec();
+            return Err(PngError::MissingFragments {
+                more: count - present - missing.len() as u32,
+                missing,
+                count,
+            });
+        }
+
+        Ok(fragments.into_iter().flat_map(|f| f.data).collect())
+    }
+
+    /// Returns true if `data` starts with the fragment magic bytes.
+    pub fn is_fragment(data: &[u8]) -> bool {
+        data.starts_with(&Fragment::MAGIC)
+    }
+
+    /// The id shared by all fragments of the same sequence.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The position of this fragment in its sequence, starting at 0.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// The number of fragments in the sequence.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// The piece of data carried by this fragment.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns this fragment as a byte sequence.
+    /// The following data is included in this byte sequence in order:
+    /// 1. Magic bytes `PWfr` *(4 bytes)*
+    /// 2. Sequence id *(4 bytes)*
+    /// 3. Index of this fragment *(4 bytes)*
+    /// 4. Number of fragments in the sequence *(4 bytes)*
+    /// 5. The data itself
+    pub fn as_bytes(&self) -> Vec<u8> {
+        Fragment::MAGIC
+            .into_iter()
+            .chain(self.id.to_be_bytes())
+            .chain(self.index.to_be_bytes())
+            .chain(self.count.to_be_bytes())
+            .chain(self.data.iter().copied())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &[u8] = b"This is where your secret message will be!";
+
+    #[test]
+    fn test_split_reassemble() {
+        let fragments = Fragment::split(DATA, Fragment::HEADER_LEN + 10).unwrap();
+        assert_eq!(fragments.len(), 5);
+        assert!(fragments
+            .iter()
+            .all(|f| f.as_bytes().len() <= Fragment::HEADER_LEN + 10));
+
+        let parsed = fragments
+            .iter()
+            .rev()
+            .map(|f| Fragment::try_from(f.as_bytes().as_ref()).unwrap())
+            .collect_vec();
+        assert_eq!(parsed[0].index(), 4);
+        assert_eq!(parsed[0].count(), 5);
+        assert_eq!(Fragment::reassemble(parsed).unwrap(), DATA);
+    }
+
+    #[test]
+    fn test_split_too_small() {
+        assert!(Fragment::split(DATA, Fragment::HEADER_LEN).is_err());
+    }
+
+    #[test]
+    fn test_reassemble_missing() {
+        let mut fragments = Fragment::split(DATA, Fragment::HEADER_LEN + 10).unwrap();
+        fragments.remove(2);
+        fragments.remove(0);
+        let err = Fragment::reassemble(fragments).unwrap_err();
+        assert!(matches!(
+            &err,
+            PngError::MissingFragments { missing, more: 0, count: 5 } if missing == &[0, 2]
+        ));
+        assert_eq!(err.to_string(), "Missing fragments 1, 3 of 5");
+    }
+
+    #[test]
+    fn test_reassemble_huge_count() {
+        let fragment = Fragment {
+            id: 1,
+            index: 1,
+            count: u32::MAX,
+            data: DATA.into(),
+        };
+        let err = Fragment::reassemble(vec![fragment]).unwrap_err();
+        assert!(matches!(
+            &err,
+            PngError::MissingFragments { missing, more, count: u32::MAX }
+                if missing.len() == Fragment::MAX_LISTED_MISSING
+                    && missing[..2] == [0, 2]
+                    && *more == u32::MAX - 11
+        ));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Missing fragments 1, 3, 4, 5, 6, 7, 8, 9, 10, 11 and {} more of {}",
+                u32::MAX - 11,
+                u32::MAX
+            )
+        );
+    }
+
+    #[test]
+    fn test_reassemble_duplicate() {
+        let mut fragments = Fragment::split(DATA, Fragment::HEADER_LEN + 10).unwrap();
+        fragments.push(fragments[1].clone());
+        let err = Fragment::reassemble(fragments).unwrap_err();
+        assert!(matches!(
+            err,
+            PngError::DuplicateFragment { index: 1, count: 5 }
+        ));
+        assert_eq!(err.to_string(), "Duplicate fragment 2 of 5");
+    }
+
+    #[test]
+    fn test_invalid_fragment() {
+        assert!(Fragment::try_from(DATA).is_err());
+
+        let mut bytes = Fragment::split(DATA, 64).unwrap()[0].as_bytes();
+        bytes[8..12].copy_from_slice(&1u32.to_be_bytes());
+        assert!(Fragment::try_from(bytes.as_ref()).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use std::fmt::{self, Display};
+
+use crate::error::{PngError, Result};
+
+use super::{chunk::Chunk, chunk_type::ChunkType};
+
+/// How the pixels of an image are laid out. \
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.IHDR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayscaleAlpha = 4,
+    Rgba = 6,
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(PngError::InvalidIhdr(format!(
+                "unknown colour type {}",
+                value
+            ))),
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale + alpha",
+            ColorType::Rgba => "RGBA",
+        })
+    }
+}
+
+impl ColorType {
+    /// Number of samples making up a single pixel.
+    pub fn channels(&self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// The bit depths allowed for this colour type.
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+/// The image header stored in the `IHDR` chunk, which must come first in a PNG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    compression_method: u8,
+    filter_method: u8,
+    interlaced: bool,
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = PngError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let value: &[u8; Ihdr::LENGTH] = value.try_into().map_err(|_| {
+            PngError::InvalidIhdr(format!(
+                "expected {} bytes but found {}",
+                Ihdr::LENGTH,
+                value.len()
+            ))
+        })?;
+
+        let interlaced = match value[12] {
+            0 => false,
+            1 => true,
+            method => {
+                return Err(PngError::InvalidIhdr(format!(
+                    "unknown interlace method {}",
+                    method
+                )))
+            }
+        };
+        Ihdr::new(
+            u32::from_be_bytes(value[0..4].try_into().unwrap()),
+            u32::from_be_bytes(value[4..8].try_into().unwrap()),
+            value[8],
+            ColorType::try_from(value[9])?,
+            value[10],
+            value[11],
+            interlaced,
+        )
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = PngError;
+
+    fn try_from(value: &Chunk) -> Result<Self, Self::Error> {
+        if value.chunk_type() != &ChunkType::IHDR {
+            return Err(PngError::InvalidIhdr(format!(
+                "'{}' is not an 'IHDR' chunk",
+                value.chunk_type()
+            )));
+        }
+        Ihdr::try_from(value.data())
+    }
+}
+
+impl Display for Ihdr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}, {}-bit {}, {}",
+            self.width,
+            self.height,
+            self.bit_depth,
+            self.color_type,
+            if self.interlaced {
+                "Adam7 interlaced"
+            } else {
+                "non-interlaced"
+            }
+        )
+    }
+}
+
+impl Ihdr {
+    /// Length of the `IHDR` chunk data.
+    pub const LENGTH: usize = 13;
+
+    /// Creates a validated `Ihdr`. The dimensions must be non-zero and fit into 31 bits,
+    /// the bit depth must be legal for `color_type` and only method `0` is defined for
+    /// compression and filtering.
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+        compression_method: u8,
+        filter_method: u8,
+        interlaced: bool,
+    ) -> Result<Ihdr> {
+        for (name, value) in [("width", width), ("height", height)] {
+            if value == 0 || value > i32::MAX as u32 {
+                return Err(PngError::InvalidIhdr(format!(
+                    "{} must be between 1 and {} but is {}",
+                    name,
+                    i32::MAX,
+                    value
+                )));
+            }
+        }
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(PngError::InvalidIhdr(format!(
+                "bit depth {} is not allowed for {} images",
+                bit_depth, color_type
+            )));
+        }
+        if compression_method != 0 {
+            return Err(PngError::InvalidIhdr(format!(
+                "unknown compression method {}",
+                compression_method
+            )));
+        }
+        if filter_method != 0 {
+            return Err(PngError::InvalidIhdr(format!(
+                "unknown filter method {}",
+                filter_method
+            )));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method,
+            filter_method,
+            interlaced,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Number of bits per sample, or per palette index for indexed images.
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    pub fn compression_method(&self) -> u8 {
+        self.compression_method
+    }
+
+    pub fn filter_method(&self) -> u8 {
+        self.filter_method
+    }
+
+    /// Whether the image data is stored using Adam7 interlacing.
+    pub fn interlaced(&self) -> bool {
+        self.interlaced
+    }
+
+    /// Number of bits making up a single pixel.
+    pub fn bits_per_pixel(&self) -> usize {
+        self.bit_depth as usize * self.color_type.channels() as usize
+    }
+
+    /// Returns this header as the 13 bytes stored in an `IHDR` chunk.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.width
+            .to_be_bytes()
+            .into_iter()
+            .chain(self.height.to_be_bytes())
+            .chain([
+                self.bit_depth,
+                self.color_type as u8,
+                self.compression_method,
+                self.filter_method,
+                self.interlaced as u8,
+            ])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IHDR: [u8; 13] = [0, 0, 1, 0, 0, 0, 0, 200, 8, 6, 0, 0, 1];
+
+    #[test]
+    fn test_ihdr_from_bytes() {
+        let ihdr = Ihdr::try_from(IHDR.as_ref()).unwrap();
+        assert_eq!(ihdr.width(), 256);
+        assert_eq!(ihdr.height(), 200);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::Rgba);
+        assert!(ihdr.interlaced());
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+        assert_eq!(ihdr.as_bytes(), IHDR);
+        assert_eq!(ihdr.to_string(), "256x200, 8-bit RGBA, Adam7 interlaced");
+    }
+
+    #[test]
+    fn test_ihdr_from_chunk() {
+        let chunk = Chunk::new(ChunkType::IHDR, IHDR.into());
+        assert!(Ihdr::try_from(&chunk).is_ok());
+
+        let chunk = Chunk::new(ChunkType::IDAT, IHDR.into());
+        assert!(Ihdr::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        assert!(Ihdr::try_from(&IHDR[..12]).is_err());
+
+        let invalid = |offset: usize, value: u8| {
+            let mut bytes = IHDR;
+            bytes[offset] = value;
+            Ihdr::try_from(bytes.as_ref()).is_err()
+        };
+        assert!(invalid(2, 0)); // zero width
+        assert!(invalid(4, 0x80)); // height above 2^31 - 1
+        assert!(invalid(8, 4)); // 4-bit RGBA
+        assert!(invalid(9, 1)); // unknown colour type
+        assert!(invalid(10, 1));
+        assert!(invalid(11, 1));
+        assert!(invalid(12, 2));
+    }
+
+    #[test]
+    fn test_allowed_bit_depths() {
+        assert!(Ihdr::new(1, 1, 16, ColorType::Grayscale, 0, 0, false).is_ok());
+        assert!(Ihdr::new(1, 1, 1, ColorType::Grayscale, 0, 0, false).is_ok());
+        assert!(Ihdr::new(1, 1, 16, ColorType::Indexed, 0, 0, false).is_err());
+        assert!(Ihdr::new(1, 1, 2, ColorType::Rgb, 0, 0, false).is_err());
+        assert!(Ihdr::new(1, 1, 16, ColorType::GrayscaleAlpha, 0, 0, false).is_ok());
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use crate::{
+    crypto,
+    error::{PngError, Result},
+};
+
+use super::{
+    envelope::Envelope,
+    ihdr::{ColorType, Ihdr},
+    pixels::Image,
+    Png,
+};
+
+/// The colour channels whose least significant bits carry hidden data.
+/// Grayscale samples are used if any of red, green or blue is selected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channels {
+    red: bool,
+    green: bool,
+    blue: bool,
+    alpha: bool,
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Self {
+            red: true,
+            green: true,
+            blue: true,
+            alpha: false,
+        }
+    }
+}
+
+impl FromStr for Channels {
+    type Err = PngError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut channels = Channels {
+            red: false,
+            green: false,
+            blue: false,
+            alpha: false,
+        };
+        for c in s.chars() {
+            let channel = match c.to_ascii_lowercase() {
+                'r' => &mut channels.red,
+                'g' => &mut channels.green,
+                'b' => &mut channels.blue,
+                'a' => &mut channels.alpha,
+                _ => return Err(PngError::InvalidChannels(s.into())),
+            };
+            if *channel {
+                return Err(PngError::InvalidChannels(s.into()));
+            }
+            *channel = true;
+        }
+
+        if s.is_empty() {
+            return Err(PngError::InvalidChannels(s.into()));
+        }
+        Ok(channels)
+    }
+}
+
+impl Display for Channels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (selected, c) in [
+            (self.red, 'r'),
+            (self.green, 'g'),
+            (self.blue, 'b'),
+            (self.alpha, 'a'),
+        ] {
+            if selected {
+                write!(f, "{}", c)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Channels {
+    /// Indices of the selected samples within a pixel of the given colour type.
+    fn samples(&self, color_type: ColorType) -> Result<Vec<usize>> {
+        let gray = self.red || self.green || self.blue;
+        let candidates = match color_type {
+            ColorType::Grayscale => vec![(gray, 0)],
+            ColorType::GrayscaleAlpha => vec![(gray, 0), (self.alpha, 1)],
+            ColorType::Rgb => vec![(self.red, 0), (self.green, 1), (self.blue, 2)],
+            ColorType::Rgba => vec![
+                (self.red, 0),
+                (self.green, 1),
+                (self.blue, 2),
+                (self.alpha, 3),
+            ],
+            ColorType::Indexed => {
+                return Err(PngError::UnsupportedImage(
+                    "hiding data in indexed images is not supported".into(),
+                ))
+            }
+        };
+        let samples: Vec<usize> = candidates
+            .into_iter()
+            .filter_map(|(selected, idx)| selected.then_some(idx))
+            .collect();
+
+        if samples.is_empty() {
+            return Err(PngError::UnsupportedImage(format!(
+                "{} images have none of the channels '{}'",
+                color_type, self
+            )));
+        }
+        Ok(samples)
+    }
+}
+
+/// Fails for images whose samples can't carry hidden data.
+fn check_supported(ihdr: &Ihdr) -> Result<()> {
+    if ihdr.bit_depth() < 8 {
+        return Err(PngError::UnsupportedImage(format!(
+            "hiding data in {}-bit images is not supported",
+            ihdr.bit_depth()
+        )));
+    }
+    Ok(())
+}
+
+/// Offsets of the bytes holding the least significant bit of every selected sample,
+/// in the order they are written to. The order is scattered across the image by a
+/// permutation derived from `key` if one is given.
+fn positions(image: &Image, channels: Channels, key: Option<&[u8]>) -> Result<Positions> {
+    let ihdr = image.ihdr();
+    check_supported(ihdr)?;
+
+    let samples = channels.samples(ihdr.color_type())?;
+    let len = ihdr.width() as usize * ihdr.height() as usize * samples.len();
+    let order: Box<dyn Iterator<Item = usize>> = match key {
+        Some(key) => Box::new(crypto::Permutation::new(key, len)?),
+        None => Box::new(0..len),
+    };
+    Ok(Positions {
+        samples,
+        channel_count: ihdr.color_type().channels() as usize,
+        sample_len: ihdr.bit_depth() as usize / 8,
+        len,
+        order,
+    })
+}
+
+/// The byte offsets returned by `positions`. They are computed one at a time, so that
+/// large images aren't copied and only the part of a scattered order in use is drawn.
+struct Positions {
+    samples: Vec<usize>,
+    channel_count: usize,
+    sample_len: usize,
+    len: usize,
+    /// Indices of the selected samples, in the order they are written to.
+    order: Box<dyn Iterator<Item = usize>>,
+}
+
+impl Positions {
+    /// The total number of offsets, however many have been consumed.
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Iterator for Positions {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let i = self.order.next()?;
+        let (pixel, sample) = (i / self.samples.len(), self.samples[i % self.samples.len()]);
+        Some((pixel * self.channel_count + sample + 1) * self.sample_len - 1)
+    }
+}
+
+/// Number of bytes that can be hidden in the selected channels of an image with the
+/// given header, including the envelope around the payload.
+pub fn capacity(ihdr: &Ihdr, channels: Channels) -> Result<usize> {
+    check_supported(ihdr)?;
+    let samples = channels.samples(ihdr.color_type())?.len();
+    (ihdr.width() as usize)
+        .checked_mul(ihdr.height() as usize)
+        .and_then(|pixels| pixels.checked_mul(samples))
+        .map(|bits| bits / 8)
+        .ok_or(PngError::TooLarge("Image"))
+}
+
+/// The channel selections worth considering for an image of the given colour type:
+/// every single channel, all colour channels, and all channels including alpha.
+pub fn channel_choices(color_type: ColorType) -> Vec<Channels> {
+    let choices: &[&str] = match color_type {
+        ColorType::Grayscale => &["rgb"],
+        ColorType::GrayscaleAlpha => &["rgb", "a", "rgba"],
+        ColorType::Rgb => &["r", "g", "b", "rgb"],
+        ColorType::Rgba => &["r", "g", "b", "a", "rgb", "rgba"],
+        ColorType::Indexed => &[],
+    };
+    choices
+        .iter()
+        .map(|choice| Channels::from_str(choice).unwrap())
+        .collect()
+}
+
+/// Hides `envelope` in the least significant bits of the selected channels of `png`,
+/// rewriting its `IDAT` chunks. With a `key`, the bits are scattered across the image
+/// and can only be located again with the same key.
+pub fn embed(
+    png: &mut Png,
+    envelope: &Envelope,
+    channels: Channels,
+    key: Option<&[u8]>,
+) -> Result<()> {
+    let mut image = Image::read_from(png)?;
+    let positions = positions(&image, channels, key)?;
+    let bytes = envelope.as_bytes();
+    if bytes.len() * 8 > positions.len() {
+        return Err(PngError::InsufficientCapacity {
+            needed: bytes.len(),
+            available: positions.len() / 8,
+        });
+    }
+
+    let data = image.data_mut();
+    let bits = bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1));
+    for (pos, bit) in positions.zip(bits) {
+        data[pos] = data[pos] & !1 | bit;
+    }
+    image.write_to(png)
+}
+
+/// Reads an envelope hidden by `embed` from the selected channels of `png`, using
+/// the same `key` it was embedded with.
+pub fn extract(png: &Png, channels: Channels, key: Option<&[u8]>) -> Result<Envelope> {
+    let image = Image::read_from(png)?;
+    let mut positions = positions(&image, channels, key)?;
+    let capacity = positions.len() / 8;
+    let data = image.data();
+    let mut read = |len: usize| -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                positions
+                    .by_ref()
+                    .take(8)
+                    .fold(0, |byte, pos| byte << 1 | data[pos] & 1)
+            })
+            .collect()
+    };
+
+    if capacity < Envelope::HEADER_LEN {
+        return Err(PngError::NotAnEnvelope);
+    }
+    let mut bytes = read(Envelope::HEADER_LEN);
+    let len = Envelope::body_len(&bytes)?;
+    if len > capacity - Envelope::HEADER_LEN {
+        return Err(PngError::InvalidEnvelope(format!(
+            "length of {} bytes exceeds the capacity of the image",
+            len
+        )));
+    }
+    bytes.extend(read(len));
+    Envelope::try_from(bytes.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::{
+        chunk::Chunk, chunk_type::ChunkType, compression::Compression, payload::Payload,
+    };
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    /// A small image with varied, unfiltered scanlines.
+    fn testing_png(color_type: ColorType, bit_depth: u8) -> Png {
+        let (width, height) = (24u32, 16u32);
+        let ihdr = Ihdr::new(width, height, bit_depth, color_type, 0, 0, false).unwrap();
+        let stride = width as usize * ihdr.bits_per_pixel() / 8;
+        let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+        for row in 0..height as usize {
+            let line: Vec<u8> = (0..stride).map(|i| (i * 31 + row * 17) as u8).collect();
+            encoder.write_all(&[0]).unwrap();
+            encoder.write_all(&line).unwrap();
+        }
+
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, ihdr.as_bytes()),
+            Chunk::new(ChunkType::IDAT, encoder.finish().unwrap()),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_lsb_roundtrip() {
+        let payload = Payload::Text("Hidden in the pixels".into());
+        let envelope = Envelope::seal(&payload, Compression::None, None).unwrap();
+        for (color_type, bit_depth) in [
+            (ColorType::Rgb, 8),
+            (ColorType::Rgba, 8),
+            (ColorType::Rgba, 16),
+            (ColorType::Grayscale, 8),
+            (ColorType::GrayscaleAlpha, 16),
+        ] {
+            let original = testing_png(color_type, bit_depth);
+            let mut png = original.clone();
+            embed(&mut png, &envelope, Channels::default(), None).unwrap();
+
+            let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+            assert_eq!(extract(&png, Channels::default(), None).unwrap(), envelope);
+
+            let before = Image::read_from(&original).unwrap();
+            let after = Image::read_from(&png).unwrap();
+            assert!(before
+                .data()
+                .iter()
+                .zip(after.data())
+                .all(|(a, b)| a >> 1 == b >> 1));
+        }
+    }
+
+    #[test]
+    fn test_lsb_channels() {
+        let envelope =
+            Envelope::seal(&Payload::Text("alpha".into()), Compression::None, None).unwrap();
+        let mut png = testing_png(ColorType::Rgba, 8);
+        let channels = Channels::from_str("a").unwrap();
+        embed(&mut png, &envelope, channels, None).unwrap();
+        assert_eq!(extract(&png, channels, None).unwrap(), envelope);
+        assert!(extract(&png, Channels::default(), None).is_err());
+
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!(capacity(&ihdr, channels).unwrap(), 24 * 16 / 8);
+        assert_eq!(
+            capacity(&ihdr, Channels::default()).unwrap(),
+            24 * 16 * 3 / 8
+        );
+        let rgb = testing_png(ColorType::Rgb, 8).ihdr().unwrap();
+        assert!(capacity(&rgb, channels).is_err());
+        assert_eq!(channel_choices(ColorType::GrayscaleAlpha).len(), 3);
+        assert!(Channels::from_str("rgbx").is_err());
+        assert!(Channels::from_str("rr").is_err());
+        assert_eq!(Channels::from_str("AR").unwrap().to_string(), "ra");
+    }
+
+    #[test]
+    fn test_lsb_positions() {
+        let png = testing_png(ColorType::Rgba, 16);
+        let image = Image::read_from(&png).unwrap();
+        let channels = Channels::from_str("ga").unwrap();
+        let sequential = positions(&image, channels, None).unwrap();
+        assert_eq!(sequential.len(), 24 * 16 * 2);
+        let sequential = sequential.collect::<Vec<_>>();
+        assert_eq!(sequential[..4], [3, 7, 11, 15]);
+
+        let mut scattered = positions(&image, channels, Some(b"key"))
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_ne!(scattered, sequential);
+        scattered.sort_unstable();
+        assert_eq!(scattered, sequential);
+    }
+
+    #[test]
+    fn test_lsb_insufficient_capacity() {
+        let payload = Payload::Text("x".repeat(100));
+        let envelope = Envelope::seal(&payload, Compression::None, None).unwrap();
+        let mut png = testing_png(ColorType::Grayscale, 8);
+        assert!(matches!(
+            embed(&mut png, &envelope, Channels::default(), None),
+            Err(PngError::InsufficientCapacity {
+                needed: 115,
+                available: 48
+            })
+        ));
+        assert!(matches!(
+            extract(&png, Channels::default(), None),
+            Err(PngError::NotAnEnvelope)
+        ));
+    }
+
+    #[test]
+    fn test_lsb_scattered() {
+        let payload = Payload::Text("Scattered across the image".into());
+        let envelope = Envelope::seal(&payload, Compression::None, None).unwrap();
+        let original = testing_png(ColorType::Rgb, 8);
+        let mut png = original.clone();
+        embed(&mut png, &envelope, Channels::default(), Some(b"hunter2")).unwrap();
+
+        assert_eq!(
+            extract(&png, Channels::default(), Some(b"hunter2")).unwrap(),
+            envelope
+        );
+        assert!(extract(&png, Channels::default(), Some(b"hunter3")).is_err());
+        assert!(extract(&png, Channels::default(), None).is_err());
+
+        // Unlike sequential embedding, the last rows of the image are touched too.
+        let before = Image::read_from(&original).unwrap();
+        let after = Image::read_from(&png).unwrap();
+        let half = before.data().len() / 2;
+        assert_ne!(before.data()[half..], after.data()[half..]);
+    }
+
+    #[test]
+    fn test_lsb_interlaced() {
+        let ihdr = Ihdr::new(24, 16, 8, ColorType::Rgb, 0, 0, true).unwrap();
+        let data = (0..24 * 16 * 3).map(|i| (i * 7) as u8).collect();
+        let mut png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::IHDR, ihdr.as_bytes()),
+            Chunk::new(ChunkType::IEND, vec![]),
+        ]);
+        png.set_image(&Image::new(ihdr, data).unwrap()).unwrap();
+
+        let envelope =
+            Envelope::seal(&Payload::Text("Adam7".into()), Compression::None, None).unwrap();
+        embed(&mut png, &envelope, Channels::default(), None).unwrap();
+        let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert!(png.ihdr().unwrap().interlaced());
+        assert_eq!(extract(&png, Channels::default(), None).unwrap(), envelope);
+    }
+}
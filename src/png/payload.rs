@@ -0,0 +1,178 @@
+use std::fmt::{self, Display};
+
+use crate::error::{PngError, Result};
+
+/// The kind of content stored in a `Payload`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    Text = 0,
+    File = 1,
+}
+
+impl TryFrom<u8> for ContentType {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ContentType::Text),
+            1 => Ok(ContentType::File),
+            _ => Err(PngError::UnknownContentType(value)),
+        }
+    }
+}
+
+/// The content hidden inside a PNG image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    /// A UTF-8 text message.
+    Text(String),
+    /// Arbitrary bytes, along with the name of the file they were read from, if any.
+    File { name: Option<String>, data: Vec<u8> },
+}
+
+impl Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Payload::Text(text) => write!(f, "\"{}\"", text),
+            Payload::File { name, data } => write!(
+                f,
+                "<file {}, {} bytes>",
+                name.as_deref()
+                    .map(|name| format!("\"{}\"", name))
+                    .unwrap_or_else(|| "without name".into()),
+                data.len()
+            ),
+        }
+    }
+}
+
+impl Payload {
+    /// Parses a payload body, as returned by `Payload::body`, of the given `content_type`.
+    pub fn from_body(content_type: ContentType, body: &[u8]) -> Result<Payload> {
+        match content_type {
+            ContentType::Text => Ok(Payload::Text(std::str::from_utf8(body)?.to_owned())),
+            ContentType::File => {
+                let name_len = u16::from_be_bytes(
+                    body.get(..2)
+                        .map(|x| <[u8; 2]>::try_from(x).unwrap())
+                        .ok_or_else(|| PngError::InvalidPayload("no file name length".into()))?,
+                ) as usize;
+                let name = body
+                    .get(2..2 + name_len)
+                    .ok_or_else(|| PngError::InvalidPayload("no file name".into()))?;
+                let size = u64::from_be_bytes(
+                    body.get(2 + name_len..2 + name_len + 8)
+                        .map(|x| <[u8; 8]>::try_from(x).unwrap())
+                        .ok_or_else(|| PngError::InvalidPayload("no file size".into()))?,
+                );
+                let data = &body[2 + name_len + 8..];
+                if data.len() as u64 != size {
+                    return Err(PngError::InvalidPayload(format!(
+                        "file size mismatch, expected {} bytes but found {}",
+                        size,
+                        data.len()
+                    )));
+                }
+
+                Ok(Payload::File {
+                    name: (!name.is_empty())
+                        .then(|| std::str::from_utf8(name).map(str::to_owned))
+                        .transpose()?,
+                    data: data.into(),
+                })
+            }
+        }
+    }
+
+    /// The `ContentType` of this payload.
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            Payload::Text(_) => ContentType::Text,
+            Payload::File { .. } => ContentType::File,
+        }
+    }
+
+    /// The raw content of this payload, without any metadata.
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Payload::Text(text) => text.as_bytes(),
+            Payload::File { data, .. } => data,
+        }
+    }
+
+    /// Returns this payload as a byte sequence, depending on its `ContentType`:
+    /// - Text: The UTF-8 bytes of the message
+    /// - File: The length of the name *(2 bytes)*, the name itself,
+    ///   the size of the data *(8 bytes)* and the data
+    pub fn body(&self) -> Result<Vec<u8>> {
+        Ok(match self {
+            Payload::Text(text) => text.as_bytes().into(),
+            Payload::File { name, data } => {
+                let name = name.as_deref().unwrap_or_default().as_bytes();
+                let name_len =
+                    u16::try_from(name.len()).map_err(|_| PngError::TooLarge("File name"))?;
+                name_len
+                    .to_be_bytes()
+                    .into_iter()
+                    .chain(name.iter().copied())
+                    .chain((data.len() as u64).to_be_bytes())
+                    .chain(data.iter().copied())
+                    .collect()
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_roundtrip() {
+        let payload = Payload::Text("Hello, world!".into());
+        let body = payload.body().unwrap();
+        assert_eq!(
+            Payload::from_body(payload.content_type(), &body).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_file_roundtrip() {
+        let payload = Payload::File {
+            name: Some("secret.bin".into()),
+            data: vec![0, 159, 146, 150, 255],
+        };
+        let body = payload.body().unwrap();
+        assert_eq!(
+            Payload::from_body(payload.content_type(), &body).unwrap(),
+            payload
+        );
+
+        let payload = Payload::File {
+            name: None,
+            data: vec![],
+        };
+        let body = payload.body().unwrap();
+        assert_eq!(
+            Payload::from_body(payload.content_type(), &body).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_invalid_payload() {
+        assert!(ContentType::try_from(7).is_err());
+        assert!(Payload::from_body(ContentType::Text, &[255]).is_err());
+        assert!(Payload::from_body(ContentType::File, &[]).is_err());
+
+        let mut body = Payload::File {
+            name: Some("a".into()),
+            data: vec![1, 2, 3],
+        }
+        .body()
+        .unwrap();
+        body.pop();
+        assert!(Payload::from_body(ContentType::File, &body).is_err());
+    }
+}
@@ -0,0 +1,549 @@
+use std::io::{Read, Write};
+
+use crate::error::{PngError, Result};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder};
+
+use super::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    ihdr::{ColorType, Ihdr},
+    Png,
+};
+
+/// The filter applied to a scanline before compression, stored in the byte preceding it. \
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Filters.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4,
+}
+
+impl TryFrom<u8> for FilterType {
+    type Error = PngError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(FilterType::None),
+            1 => Ok(FilterType::Sub),
+            2 => Ok(FilterType::Up),
+            3 => Ok(FilterType::Average),
+            4 => Ok(FilterType::Paeth),
+            _ => Err(PngError::InvalidImageData(format!(
+                "unknown filter type {}",
+                value
+            ))),
+        }
+    }
+}
+
+impl FilterType {
+    pub const ALL: [FilterType; 5] = [
+        FilterType::None,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Average,
+        FilterType::Paeth,
+    ];
+
+    /// Filters an unfiltered scanline, given the unfiltered previous scanline of the
+    /// same pass and the distance `bpp` in bytes to the corresponding byte of the
+    /// previous pixel, which is at least 1.
+    pub fn filter(&self, line: &[u8], prev: Option<&[u8]>, bpp: usize) -> Vec<u8> {
+        let up = |i: usize| prev.map_or(0, |prev| prev[i]);
+        let left = |i: usize| if i >= bpp { line[i - bpp] } else { 0 };
+        let up_left = |i: usize| if i >= bpp { up(i - bpp) } else { 0 };
+        (0..line.len())
+            .map(|i| {
+                let predicted = match self {
+                    FilterType::None => 0,
+                    FilterType::Sub => left(i),
+                    FilterType::Up => up(i),
+                    FilterType::Average => ((left(i) as u16 + up(i) as u16) / 2) as u8,
+                    FilterType::Paeth => paeth(left(i), up(i), up_left(i)),
+                };
+                line[i].wrapping_sub(predicted)
+            })
+            .collect()
+    }
+
+    /// Reverses `FilterType::filter` in place.
+    pub fn unfilter(&self, line: &mut [u8], prev: Option<&[u8]>, bpp: usize) {
+        let up = |i: usize| prev.map_or(0, |prev| prev[i]);
+        match self {
+            FilterType::None => {}
+            FilterType::Sub => {
+                for i in bpp..line.len() {
+                    line[i] = line[i].wrapping_add(line[i - bpp]);
+                }
+            }
+            FilterType::Up => {
+                for (i, byte) in line.iter_mut().enumerate() {
+                    *byte = byte.wrapping_add(up(i));
+                }
+            }
+            FilterType::Average => {
+                for i in 0..line.len() {
+                    let left = if i >= bpp { line[i - bpp] } else { 0 };
+                    line[i] = line[i].wrapping_add(((left as u16 + up(i) as u16) / 2) as u8);
+                }
+            }
+            FilterType::Paeth => {
+                for i in 0..line.len() {
+                    let (left, up_left) = if i >= bpp {
+                        (line[i - bpp], up(i - bpp))
+                    } else {
+                        (0, 0)
+                    };
+                    line[i] = line[i].wrapping_add(paeth(left, up(i), up_left));
+                }
+            }
+        }
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// A reduced image making up part of the image data. Non-interlaced images consist of
+/// a single pass covering every pixel, Adam7 interlaced images of seven.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pass {
+    x: usize,
+    y: usize,
+    dx: usize,
+    dy: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Pass {
+    /// Starting column, starting row, column spacing and row spacing of the Adam7 passes.
+    const ADAM7: [(usize, usize, usize, usize); 7] = [
+        (0, 0, 8, 8),
+        (4, 0, 8, 8),
+        (0, 4, 4, 8),
+        (2, 0, 4, 4),
+        (0, 2, 2, 4),
+        (1, 0, 2, 2),
+        (0, 1, 1, 2),
+    ];
+
+    /// The passes of an image, leaving out the empty ones which take up no bytes at all.
+    fn of(ihdr: &Ihdr) -> Vec<Pass> {
+        let (width, height) = (ihdr.width() as usize, ihdr.height() as usize);
+        if !ihdr.interlaced() {
+            return vec![Pass {
+                x: 0,
+                y: 0,
+                dx: 1,
+                dy: 1,
+                width,
+                height,
+            }];
+        }
+
+        Pass::ADAM7
+            .into_iter()
+            .map(|(x, y, dx, dy)| Pass {
+                x,
+                y,
+                dx,
+                dy,
+                width: (width + dx - 1).saturating_sub(x) / dx,
+                height: (height + dy - 1).saturating_sub(y) / dy,
+            })
+            .filter(|pass| pass.width > 0 && pass.height > 0)
+            .collect()
+    }
+
+    fn is_full(&self) -> bool {
+        self.dx == 1 && self.dy == 1
+    }
+}
+
+/// Copies the pixel at index `from` of the scanline `src` to index `to` of `dst`,
+/// for pixels of any size including those packed into fractions of a byte.
+fn copy_pixel(src: &[u8], from: usize, dst: &mut [u8], to: usize, bits_per_pixel: usize) {
+    if bits_per_pixel >= 8 {
+        let len = bits_per_pixel / 8;
+        dst[to * len..(to + 1) * len].copy_from_slice(&src[from * len..(from + 1) * len]);
+    } else {
+        let mask = (1u8 << bits_per_pixel) - 1;
+        let shift = |idx: usize| 8 - bits_per_pixel - idx * bits_per_pixel % 8;
+        let value = src[from * bits_per_pixel / 8] >> shift(from) & mask;
+        let byte = &mut dst[to * bits_per_pixel / 8];
+        *byte = *byte & !(mask << shift(to)) | value << shift(to);
+    }
+}
+
+/// The unfiltered pixels of a PNG, one scanline after another without the leading filter
+/// type bytes. Interlaced images are stored de-interlaced, so that rows always follow
+/// the same layout; pixels smaller than a byte are packed as in the PNG itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    ihdr: Ihdr,
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// Smallest size of the `IDAT` chunks written back by `Image::write_to`.
+    const MIN_IDAT_LENGTH: usize = 8192;
+
+    /// Creates an image from unfiltered scanlines laid out as described by `ihdr`.
+    pub fn new(ihdr: Ihdr, data: Vec<u8>) -> Result<Image> {
+        let expected = Image::len(&ihdr)?;
+        if data.len() != expected {
+            return Err(PngError::InvalidImageData(format!(
+                "expected {} bytes of scanlines but found {}",
+                expected,
+                data.len()
+            )));
+        }
+        Ok(Self { ihdr, data })
+    }
+
+    /// Inflates and unfilters the concatenated `IDAT` chunks of `png`.
+    pub fn read_from(png: &Png) -> Result<Image> {
+        let compressed: Vec<u8> = png
+            .chunks_by_type(&ChunkType::IDAT)
+            .into_iter()
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect();
+        if compressed.is_empty() {
+            return Err(PngError::ChunkNotFound(ChunkType::IDAT));
+        }
+        Image::decode(png.ihdr()?, &compressed)
+    }
+
+    /// Inflates and unfilters a zlib stream of image data described by `ihdr`.
+    pub fn decode(ihdr: Ihdr, compressed: &[u8]) -> Result<Image> {
+        let passes = Pass::of(&ihdr);
+        let expected = passes
+            .iter()
+            .try_fold(0usize, |sum, pass| {
+                (Image::row_len(&ihdr, pass.width) + 1)
+                    .checked_mul(pass.height)
+                    .and_then(|len| sum.checked_add(len))
+            })
+            .ok_or(PngError::TooLarge("Image"))?;
+
+        // The header can claim any size, so the buffer only grows with the data found
+        let mut filtered = vec![];
+        ZlibDecoder::new(compressed)
+            .take(expected as u64 + 1)
+            .read_to_end(&mut filtered)
+            .map_err(|err| PngError::InvalidImageData(err.to_string()))?;
+        if filtered.len() != expected {
+            return Err(PngError::InvalidImageData(format!(
+                "expected {} bytes of image data but found {}",
+                expected,
+                filtered.len()
+            )));
+        }
+
+        let stride = Image::stride(&ihdr);
+        let bpp = Image::filter_distance(&ihdr);
+        let mut data = vec![0u8; Image::len(&ihdr)?];
+        let mut rest = filtered.as_slice();
+        for pass in passes {
+            let row_len = Image::row_len(&ihdr, pass.width);
+            let (filtered, remaining) = rest.split_at((row_len + 1) * pass.height);
+            rest = remaining;
+
+            let mut lines = vec![0u8; row_len * pass.height];
+            for (row, line) in filtered.chunks_exact(row_len + 1).enumerate() {
+                let (before, current) = lines.split_at_mut(row * row_len);
+                let current = &mut current[..row_len];
+                current.copy_from_slice(&line[1..]);
+                let prev = (row > 0).then(|| &before[(row - 1) * row_len..]);
+                FilterType::try_from(line[0])?.unfilter(current, prev, bpp);
+            }
+
+            if pass.is_full() {
+                data = lines;
+                continue;
+            }
+            for (row, line) in lines.chunks_exact(row_len).enumerate() {
+                let y = pass.y + row * pass.dy;
+                let target = &mut data[y * stride..(y + 1) * stride];
+                for col in 0..pass.width {
+                    let x = pass.x + col * pass.dx;
+                    copy_pixel(line, col, target, x, ihdr.bits_per_pixel());
+                }
+            }
+        }
+
+        Ok(Self { ihdr, data })
+    }
+
+    /// Filters and deflates this image into a zlib stream, interlacing it first if the
+    /// header says so. Images of 8 bits per sample or more choose the filter of each
+    /// scanline adaptively, others aren't filtered as recommended by the PNG spec.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let ihdr = &self.ihdr;
+        let stride = Image::stride(ihdr);
+        let bpp = Image::filter_distance(ihdr);
+        let adaptive = ihdr.bit_depth() >= 8 && ihdr.color_type() != ColorType::Indexed;
+
+        let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::best());
+        for pass in Pass::of(ihdr) {
+            let row_len = Image::row_len(ihdr, pass.width);
+            let lines = if pass.is_full() {
+                self.data.clone()
+            } else {
+                let mut lines = vec![0u8; row_len * pass.height];
+                for (row, line) in lines.chunks_exact_mut(row_len).enumerate() {
+                    let y = pass.y + row * pass.dy;
+                    let source = &self.data[y * stride..(y + 1) * stride];
+                    for col in 0..pass.width {
+                        let x = pass.x + col * pass.dx;
+                        copy_pixel(source, x, line, col, ihdr.bits_per_pixel());
+                    }
+                }
+                lines
+            };
+
+            let mut prev: Option<&[u8]> = None;
+            for line in lines.chunks_exact(row_len) {
+                let (filter_type, filtered) = if adaptive {
+                    FilterType::ALL
+                        .into_iter()
+                        .map(|filter_type| (filter_type, filter_type.filter(line, prev, bpp)))
+                        .min_by_key(|(_, filtered)| {
+                            filtered
+                                .iter()
+                                .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                                .sum::<u64>()
+                        })
+                        .unwrap()
+                } else {
+                    (FilterType::None, line.to_vec())
+                };
+                encoder.write_all(&[filter_type as u8])?;
+                encoder.write_all(&filtered)?;
+                prev = Some(line);
+            }
+        }
+        Ok(encoder.finish()?)
+    }
+
+    /// Encodes this image, replacing the `IDAT` chunks of `png` with the result.
+    /// The new chunks take the place of the first old one, or go before `IEND`
+    /// if there are none.
+    pub fn write_to(&self, png: &mut Png) -> Result<()> {
+        let compressed = self.encode()?;
+        let idx = match png
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type() == &ChunkType::IDAT)
+        {
+            Some(idx) => idx,
+            None => png.resolve_position(super::ChunkPosition::BeforeIend)?,
+        };
+        let chunk_len = png
+            .chunks_by_type(&ChunkType::IDAT)
+            .iter()
+            .map(|chunk| chunk.length() as usize)
+            .max()
+            .unwrap_or_default()
+            .max(Image::MIN_IDAT_LENGTH);
+
+        png.chunks
+            .retain(|chunk| chunk.chunk_type() != &ChunkType::IDAT);
+        png.chunks.splice(
+            idx..idx,
+            compressed
+                .chunks(chunk_len)
+                .map(|data| Chunk::new(ChunkType::IDAT, data.into())),
+        );
+        Ok(())
+    }
+
+    pub fn ihdr(&self) -> &Ihdr {
+        &self.ihdr
+    }
+
+    /// The unfiltered scanlines.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The unfiltered scanline at row `y`.
+    pub fn row(&self, y: usize) -> &[u8] {
+        let stride = Image::stride(&self.ihdr);
+        &self.data[y * stride..(y + 1) * stride]
+    }
+
+    /// Number of bytes in a scanline, excluding the filter type byte.
+    pub fn stride(ihdr: &Ihdr) -> usize {
+        Image::row_len(ihdr, ihdr.width() as usize)
+    }
+
+    /// Number of bytes in all of the unfiltered scanlines of an image.
+    fn len(ihdr: &Ihdr) -> Result<usize> {
+        Image::stride(ihdr)
+            .checked_mul(ihdr.height() as usize)
+            .ok_or(PngError::TooLarge("Image"))
+    }
+
+    /// Number of bytes in a scanline of `width` pixels.
+    fn row_len(ihdr: &Ihdr, width: usize) -> usize {
+        (width * ihdr.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Distance in bytes to the corresponding byte of the previous pixel, at least 1.
+    fn filter_distance(ihdr: &Ihdr) -> usize {
+        ihdr.bits_per_pixel().div_ceil(8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image whose every pixel differs, so that misplaced pixels are noticed.
+    fn testing_image(color_type: ColorType, bit_depth: u8, interlaced: bool) -> Image {
+        let ihdr = Ihdr::new(13, 11, bit_depth, color_type, 0, 0, interlaced).unwrap();
+        let stride = Image::stride(&ihdr);
+        let mut data: Vec<u8> = (0..stride * 11).map(|i| (i * 37 % 251) as u8).collect();
+        // Bits past the last pixel of a scanline aren't preserved by interlacing.
+        let padding = stride * 8 - 13 * ihdr.bits_per_pixel();
+        for line in data.chunks_exact_mut(stride) {
+            line[stride - 1] &= 0xff << padding;
+        }
+        Image::new(ihdr, data).unwrap()
+    }
+
+    #[test]
+    fn test_filter_roundtrip() {
+        let prev: Vec<u8> = (0..24).map(|i| i * 7).collect();
+        let line: Vec<u8> = (0..24).map(|i| 255 - i * 3).collect();
+        for filter_type in FilterType::ALL {
+            for prev in [None, Some(prev.as_slice())] {
+                let mut filtered = filter_type.filter(&line, prev, 3);
+                filter_type.unfilter(&mut filtered, prev, 3);
+                assert_eq!(filtered, line);
+            }
+        }
+        assert!(FilterType::try_from(5).is_err());
+    }
+
+    #[test]
+    fn test_image_roundtrip() {
+        for interlaced in [false, true] {
+            for (color_type, bit_depth) in [
+                (ColorType::Grayscale, 1),
+                (ColorType::Grayscale, 2),
+                (ColorType::Indexed, 4),
+                (ColorType::Grayscale, 8),
+                (ColorType::Rgb, 8),
+                (ColorType::Rgba, 16),
+            ] {
+                let image = testing_image(color_type, bit_depth, interlaced);
+                let decoded = Image::decode(image.ihdr, &image.encode().unwrap()).unwrap();
+                assert_eq!(decoded, image, "{} {}-bit", color_type, bit_depth);
+            }
+        }
+    }
+
+    #[test]
+    fn test_adam7_layout() {
+        // An 8x8 image with every pixel set to the number of the pass it belongs to.
+        const PASSES: [[u8; 8]; 8] = [
+            [1, 6, 4, 6, 2, 6, 4, 6],
+            [7, 7, 7, 7, 7, 7, 7, 7],
+            [5, 6, 5, 6, 5, 6, 5, 6],
+            [7, 7, 7, 7, 7, 7, 7, 7],
+            [3, 6, 4, 6, 3, 6, 4, 6],
+            [7, 7, 7, 7, 7, 7, 7, 7],
+            [5, 6, 5, 6, 5, 6, 5, 6],
+            [7, 7, 7, 7, 7, 7, 7, 7],
+        ];
+        // The reduced images of each pass, stored one after another without filtering.
+        let mut filtered = vec![];
+        for (pass, (width, height)) in [(1, 1), (1, 1), (2, 1), (2, 2), (4, 2), (4, 4), (8, 4)]
+            .into_iter()
+            .enumerate()
+        {
+            for _ in 0..height {
+                filtered.push(FilterType::None as u8);
+                filtered.extend(vec![pass as u8 + 1; width]);
+            }
+        }
+        let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&filtered).unwrap();
+
+        let ihdr = Ihdr::new(8, 8, 8, ColorType::Grayscale, 0, 0, true).unwrap();
+        let image = Image::decode(ihdr, &encoder.finish().unwrap()).unwrap();
+        assert_eq!(image.data(), PASSES.concat());
+        assert_eq!(image.row(4), PASSES[4]);
+        assert_eq!(
+            Image::decode(ihdr, &image.encode().unwrap()).unwrap(),
+            image
+        );
+    }
+
+    #[test]
+    fn test_copy_pixel() {
+        let src = [0b1011_0100];
+        let mut dst = [0b0000_0000];
+        copy_pixel(&src, 0, &mut dst, 3, 2);
+        assert_eq!(dst, [0b0000_0010]);
+        copy_pixel(&src, 1, &mut dst, 0, 2);
+        assert_eq!(dst, [0b1100_0010]);
+    }
+
+    #[test]
+    fn test_invalid_image() {
+        let ihdr = Ihdr::new(4, 4, 8, ColorType::Rgb, 0, 0, false).unwrap();
+        assert!(Image::new(ihdr, vec![0; 47]).is_err());
+        let image = Image::new(ihdr, vec![0; 48]).unwrap();
+        let mut encoded = image.encode().unwrap();
+        assert!(Image::decode(ihdr, &encoded[..encoded.len() - 4]).is_err());
+        encoded.truncate(2);
+        assert!(Image::decode(ihdr, &encoded).is_err());
+    }
+
+    #[test]
+    fn test_huge_image() {
+        let ihdr = Ihdr::new(0x7fffffff, 0x7fffffff, 16, ColorType::Rgba, 0, 0, false).unwrap();
+        let image = Image::new(
+            Ihdr::new(1, 1, 8, ColorType::Rgb, 0, 0, false).unwrap(),
+            vec![0; 3],
+        );
+        let encoded = image.unwrap().encode().unwrap();
+        assert!(matches!(
+            Image::decode(ihdr, &encoded),
+            Err(PngError::InvalidImageData(_) | PngError::TooLarge(_))
+        ));
+        let ihdr = Ihdr::new(0x7fffffff, 0x7fffff, 8, ColorType::Rgba, 0, 0, false).unwrap();
+        assert!(matches!(
+            Image::decode(ihdr, &encoded),
+            Err(PngError::InvalidImageData(_))
+        ));
+    }
+}
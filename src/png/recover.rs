@@ -0,0 +1,195 @@
+use std::ops::Range;
+
+use super::{
+    chunk::{Chunk, ReadOutcome},
+    chunk_type::ChunkType,
+    Png,
+};
+
+/// The result of `Png::recover`: everything that could be salvaged from a damaged
+/// PNG stream, along with what had to be skipped to get there.
+#[derive(Debug, Clone)]
+pub struct Recovery {
+    png: Png,
+    signature_damaged: bool,
+    skipped: Vec<Range<usize>>,
+}
+
+impl Recovery {
+    /// The recovered image. Chunks with bad CRCs are kept as they are, and anything
+    /// after `IEND` or after the last plausible chunk is kept as trailing data.
+    pub fn png(&self) -> &Png {
+        &self.png
+    }
+
+    pub fn into_png(self) -> Png {
+        self.png
+    }
+
+    /// Whether the stream didn't start with the PNG signature.
+    pub fn signature_damaged(&self) -> bool {
+        self.signature_damaged
+    }
+
+    /// Byte ranges of the stream that didn't belong to any chunk and were dropped.
+    pub fn skipped(&self) -> &[Range<usize>] {
+        &self.skipped
+    }
+
+    /// Whether anything at all had to be recovered.
+    pub fn is_clean(&self) -> bool {
+        !self.signature_damaged
+            && self.skipped.is_empty()
+            && self.png.trailing_data().is_empty()
+            && self.png.chunks().iter().all(Chunk::has_valid_crc)
+    }
+}
+
+impl Png {
+    /// Salvages as many chunks as possible from a damaged PNG stream. Unlike
+    /// `ParseMode::Lenient`, reading doesn't stop at the first implausible chunk header:
+    /// the bytes up to the next chunk with a matching CRC are skipped instead.
+    pub fn recover(bytes: &[u8]) -> Recovery {
+        let signature_damaged = !bytes.starts_with(&Png::STANDARD_HEADER);
+        let mut chunks = vec![];
+        let mut skipped = vec![];
+        let mut pos = Png::STANDARD_HEADER.len().min(bytes.len());
+
+        while pos < bytes.len() {
+            let chunk = match plausible_chunk(&bytes[pos..]) {
+                Some(chunk) => chunk,
+                None => {
+                    let Some(next) = (pos + 1..bytes.len()).find(|&next| {
+                        plausible_chunk(&bytes[next..]).is_some_and(|chunk| chunk.has_valid_crc())
+                    }) else {
+                        break;
+                    };
+                    skipped.push(pos..next);
+                    pos = next;
+                    continue;
+                }
+            };
+            pos += chunk.length() as usize + 12;
+            let is_iend = chunk.chunk_type() == &ChunkType::IEND;
+            chunks.push(chunk);
+            if is_iend {
+                break;
+            }
+        }
+
+        let mut png = Png::from_chunks(chunks);
+        png.trailing = bytes[pos..].to_vec();
+        Recovery {
+            png,
+            signature_damaged,
+            skipped,
+        }
+    }
+
+    /// Replaces the stored CRC of every damaged chunk, returning their indices.
+    pub fn fix_crcs(&mut self) -> Vec<usize> {
+        self.chunks
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(idx, chunk)| chunk.fix_crc().then_some(idx))
+            .collect()
+    }
+}
+
+/// Reads a chunk from the start of `bytes` if its header looks like one: a type made
+/// of letters with the reserved bit unset and a length that fits into the stream. The
+/// length is checked before anything is read, so that garbage is rejected cheaply.
+fn plausible_chunk(mut bytes: &[u8]) -> Option<Chunk> {
+    let kind = bytes.get(4..8)?;
+    if !kind.iter().all(u8::is_ascii_alphabetic) || !kind[2].is_ascii_uppercase() {
+        return None;
+    }
+    let len = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+    if len > Chunk::MAX_LENGTH || 8 + len + 4 > bytes.len() {
+        return None;
+    }
+    match Chunk::read_next(&mut bytes) {
+        Ok(ReadOutcome::Chunk(chunk)) => Some(chunk),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunks() -> Vec<Chunk> {
+        ["IHDR", "ruSt", "IDAT", "IEND"]
+            .into_iter()
+            .map(|kind| Chunk::new(ChunkType::from_str(kind).unwrap(), kind.repeat(4).into()))
+            .collect()
+    }
+
+    #[test]
+    fn test_recover_clean() {
+        let png = Png::from_chunks(chunks());
+        let recovery = Png::recover(&png.as_bytes());
+        assert!(recovery.is_clean());
+        assert_eq!(recovery.png().as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_recover_bad_crc() {
+        let mut bytes = Png::from_chunks(chunks()).as_bytes();
+        // The data of 'ruSt'
+        bytes[8 + 28 + 8] ^= 1;
+        let recovery = Png::recover(&bytes);
+        assert!(!recovery.is_clean());
+        assert!(recovery.skipped().is_empty());
+        let mut png = recovery.into_png();
+        assert_eq!(png.chunks().len(), 4);
+        assert!(!png.chunks()[1].has_valid_crc());
+        assert_eq!(png.fix_crcs(), vec![1]);
+        assert!(png.chunks().iter().all(Chunk::has_valid_crc));
+        assert!(Png::try_from(png.as_bytes().as_ref()).is_ok());
+    }
+
+    #[test]
+    fn test_recover_resync() {
+        let mut bytes = Png::from_chunks(chunks()).as_bytes();
+        // The length of 'ruSt', then garbage between 'IDAT' and 'IEND'
+        bytes[8 + 28] = 0xff;
+        bytes.splice(8 + 28 * 3..8 + 28 * 3, *b"garbage");
+        bytes[..4].copy_from_slice(b"JUNK");
+        bytes.extend_from_slice(b"trailing");
+
+        let recovery = Png::recover(&bytes);
+        assert!(recovery.signature_damaged());
+        assert_eq!(
+            recovery.skipped(),
+            [8 + 28..8 + 28 * 2, 8 + 28 * 3..8 + 28 * 3 + 7]
+        );
+        let kinds = recovery
+            .png()
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, ["IHDR", "IDAT", "IEND"]);
+        assert_eq!(recovery.png().trailing_data(), b"trailing");
+    }
+
+    #[test]
+    fn test_recover_garbage_prefix() {
+        let png = Png::from_chunks(chunks());
+        // Headers claiming almost all of the stream at every offset
+        let garbage = [0x00, 0x7f, 0xff, 0xff]
+            .into_iter()
+            .chain(*b"ruSt")
+            .cycle()
+            .take(1 << 20)
+            .collect::<Vec<_>>();
+        let bytes = [&Png::STANDARD_HEADER[..], &garbage, &png.as_bytes()[8..]].concat();
+
+        let recovery = Png::recover(&bytes);
+        assert_eq!(recovery.skipped().len(), 1);
+        assert_eq!(recovery.skipped()[0], 8..8 + garbage.len());
+        assert_eq!(recovery.png().as_bytes(), png.as_bytes());
+    }
+}
@@ -0,0 +1,363 @@
+use std::{
+    fmt::{self, Display},
+    io::{Read, Write},
+};
+
+use crate::error::{PngError, Result};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder};
+
+use super::{chunk::Chunk, chunk_type::ChunkType, compression::Compression};
+
+/// Textual metadata stored in a `tEXt`, `zTXt` or `iTXt` chunk. \
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.Anc-text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    keyword: String,
+    text: String,
+    compressed: bool,
+    /// Stored as UTF-8 in an `iTXt` chunk rather than as Latin-1.
+    international: bool,
+    language_tag: String,
+    translated_keyword: String,
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = PngError;
+
+    fn try_from(value: &Chunk) -> Result<Self, Self::Error> {
+        let data = value.data();
+        let (keyword, rest) = split_nul(data, "keyword")?;
+        let keyword = validate_keyword(&decode_latin1(keyword))?;
+
+        let text = match *value.chunk_type() {
+            ChunkType::TEXT => TextChunk::new(keyword, decode_latin1(rest))?,
+            ChunkType::ZTXT => {
+                let (&method, rest) = rest
+                    .split_first()
+                    .ok_or_else(|| PngError::InvalidText("no compression method".into()))?;
+                check_method(method)?;
+                TextChunk::new(keyword, decode_latin1(&inflate(rest)?))?.with_compression(true)
+            }
+            ChunkType::ITXT => {
+                let [flag, method, rest @ ..] = rest else {
+                    return Err(PngError::InvalidText("no compression flag".into()));
+                };
+                let compressed = match flag {
+                    0 => false,
+                    1 => {
+                        check_method(*method)?;
+                        true
+                    }
+                    _ => {
+                        return Err(PngError::InvalidText(format!(
+                            "invalid compression flag {}",
+                            flag
+                        )))
+                    }
+                };
+                let (language_tag, rest) = split_nul(rest, "language tag")?;
+                let (translated_keyword, text) = split_nul(rest, "translated keyword")?;
+                let text = if compressed {
+                    String::from_utf8(inflate(text)?)
+                } else {
+                    String::from_utf8(text.into())
+                }
+                .map_err(|_| PngError::InvalidText("text is not valid UTF-8".into()))?;
+
+                TextChunk::new(keyword, text)?
+                    .with_compression(compressed)
+                    .with_language(
+                        std::str::from_utf8(language_tag)?,
+                        std::str::from_utf8(translated_keyword)?,
+                    )?
+            }
+            chunk_type => {
+                return Err(PngError::InvalidText(format!(
+                    "'{}' is not a text chunk",
+                    chunk_type
+                )))
+            }
+        };
+        Ok(text)
+    }
+}
+
+impl Display for TextChunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.keyword)?;
+        if !self.translated_keyword.is_empty() {
+            write!(f, " ({})", self.translated_keyword)?;
+        }
+        if !self.language_tag.is_empty() {
+            write!(f, " [{}]", self.language_tag)?;
+        }
+        write!(f, ": {}", self.text)
+    }
+}
+
+impl TextChunk {
+    /// Creates text to be stored in a `tEXt` chunk. Keywords are 1 to 79 printable
+    /// Latin-1 characters without leading, trailing or consecutive spaces.
+    pub fn new(keyword: impl Into<String>, text: impl Into<String>) -> Result<TextChunk> {
+        let text = text.into();
+        if text.contains('\0') {
+            return Err(PngError::InvalidText("text must not contain NUL".into()));
+        }
+
+        Ok(Self {
+            keyword: validate_keyword(&keyword.into())?,
+            text,
+            compressed: false,
+            international: false,
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+        })
+    }
+
+    /// Compresses the text, storing it in a `zTXt` chunk unless it's international.
+    pub fn with_compression(mut self, compressed: bool) -> TextChunk {
+        self.compressed = compressed;
+        self
+    }
+
+    /// Makes the text international, storing it as UTF-8 in an `iTXt` chunk along with
+    /// an RFC 3066 `language_tag` and the keyword translated into that language. Both
+    /// may be empty.
+    pub fn with_language(
+        mut self,
+        language_tag: impl Into<String>,
+        translated_keyword: impl Into<String>,
+    ) -> Result<TextChunk> {
+        let language_tag = language_tag.into();
+        if !language_tag
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+        {
+            return Err(PngError::InvalidText(format!(
+                "invalid language tag '{}'",
+                language_tag
+            )));
+        }
+        let translated_keyword = translated_keyword.into();
+        if translated_keyword.contains('\0') {
+            return Err(PngError::InvalidText(
+                "translated keyword must not contain NUL".into(),
+            ));
+        }
+
+        self.international = true;
+        self.language_tag = language_tag;
+        self.translated_keyword = translated_keyword;
+        Ok(self)
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn is_international(&self) -> bool {
+        self.international
+    }
+
+    pub fn language_tag(&self) -> &str {
+        &self.language_tag
+    }
+
+    pub fn translated_keyword(&self) -> &str {
+        &self.translated_keyword
+    }
+
+    /// The type of chunk this text is stored in.
+    pub fn chunk_type(&self) -> ChunkType {
+        match (self.international, self.compressed) {
+            (true, _) => ChunkType::ITXT,
+            (false, true) => ChunkType::ZTXT,
+            (false, false) => ChunkType::TEXT,
+        }
+    }
+
+    /// Returns true if `chunk_type` is one of the chunk types holding text.
+    pub fn is_text_chunk(chunk_type: &ChunkType) -> bool {
+        [ChunkType::TEXT, ChunkType::ZTXT, ChunkType::ITXT].contains(chunk_type)
+    }
+
+    /// Returns true if `text` can be stored in a `tEXt` or `zTXt` chunk.
+    pub fn is_latin1(text: &str) -> bool {
+        text.chars().all(|c| (c as u32) < 0x100)
+    }
+
+    /// Serializes this text into a chunk. Fails if the text isn't international
+    /// but contains characters outside of Latin-1.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let mut data = encode_latin1(&self.keyword)?;
+        data.push(0);
+
+        if self.international {
+            data.extend([self.compressed as u8, 0]);
+            data.extend(self.language_tag.bytes().chain([0]));
+            data.extend(self.translated_keyword.bytes().chain([0]));
+            if self.compressed {
+                data.extend(deflate(self.text.as_bytes())?);
+            } else {
+                data.extend(self.text.bytes());
+            }
+        } else {
+            let text = encode_latin1(&self.text)?;
+            if self.compressed {
+                data.push(0);
+                data.extend(deflate(&text)?);
+            } else {
+                data.extend(text);
+            }
+        }
+
+        if data.len() > Chunk::MAX_LENGTH {
+            return Err(PngError::TooLarge("Text"));
+        }
+        Ok(Chunk::new(self.chunk_type(), data))
+    }
+}
+
+fn validate_keyword(keyword: &str) -> Result<String> {
+    let reason = if keyword.is_empty() || keyword.chars().count() > 79 {
+        "it must be 1 to 79 characters long"
+    } else if !keyword
+        .chars()
+        .all(|c| matches!(c as u32, 0x20..=0x7e | 0xa1..=0xff))
+    {
+        "it must only contain printable Latin-1 characters"
+    } else if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        "it must not contain leading, trailing or consecutive spaces"
+    } else {
+        return Ok(keyword.into());
+    };
+    Err(PngError::InvalidText(format!(
+        "invalid keyword '{}', {}",
+        keyword, reason
+    )))
+}
+
+fn check_method(method: u8) -> Result<()> {
+    match method {
+        0 => Ok(()),
+        _ => Err(PngError::InvalidText(format!(
+            "unknown compression method {}",
+            method
+        ))),
+    }
+}
+
+/// Splits `data` at the first NUL separator, failing if there is none.
+fn split_nul<'a>(data: &'a [u8], what: &str) -> Result<(&'a [u8], &'a [u8])> {
+    let idx = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| PngError::InvalidText(format!("unterminated {}", what)))?;
+    Ok((&data[..idx], &data[idx + 1..]))
+}
+
+fn decode_latin1(data: &[u8]) -> String {
+    data.iter().map(|&byte| byte as char).collect()
+}
+
+fn encode_latin1(text: &str) -> Result<Vec<u8>> {
+    text.chars()
+        .map(|c| u8::try_from(c as u32))
+        .collect::<Result<_, _>>()
+        .map_err(|_| {
+            PngError::InvalidText(format!(
+                "'{}' contains characters outside of Latin-1, use international text instead",
+                text
+            ))
+        })
+}
+
+/// Text chunks are compressed as a zlib stream, unlike pngwhisper payloads.
+fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::best());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn inflate(data: &[u8]) -> Result<Vec<u8>> {
+    let limit = Compression::DEFAULT_LIMIT;
+    let mut buf = vec![];
+    ZlibDecoder::new(data)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut buf)?;
+    if buf.len() > limit {
+        return Err(PngError::DecompressionLimit(limit));
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(text: &TextChunk) -> TextChunk {
+        let chunk = text.to_chunk().unwrap();
+        let bytes = chunk.as_bytes();
+        TextChunk::try_from(&Chunk::try_from(bytes.as_ref()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_text_roundtrip() {
+        let text = TextChunk::new("Comment", "Café au lait").unwrap();
+        assert_eq!(text.chunk_type(), ChunkType::TEXT);
+        assert_eq!(text.to_chunk().unwrap().data(), b"Comment\0Caf\xe9 au lait");
+        assert_eq!(roundtrip(&text), text);
+    }
+
+    #[test]
+    fn test_compressed_text_roundtrip() {
+        let text = TextChunk::new("Description", "Nothing to see here. ".repeat(20))
+            .unwrap()
+            .with_compression(true);
+        assert_eq!(text.chunk_type(), ChunkType::ZTXT);
+        assert!(text.to_chunk().unwrap().data().len() < text.text().len());
+        assert_eq!(roundtrip(&text), text);
+    }
+
+    #[test]
+    fn test_international_text_roundtrip() {
+        for compressed in [false, true] {
+            let text = TextChunk::new("Title", "秘密のメッセージ")
+                .unwrap()
+                .with_compression(compressed)
+                .with_language("ja-JP", "タイトル")
+                .unwrap();
+            assert_eq!(text.chunk_type(), ChunkType::ITXT);
+            assert_eq!(roundtrip(&text), text);
+        }
+    }
+
+    #[test]
+    fn test_invalid_text() {
+        assert!(TextChunk::new("", "text").is_err());
+        assert!(TextChunk::new("k".repeat(80), "text").is_err());
+        assert!(TextChunk::new(" Title", "text").is_err());
+        assert!(TextChunk::new("Two  spaces", "text").is_err());
+        assert!(TextChunk::new("Title", "nul\0").is_err());
+        assert!(TextChunk::new("Title", "text")
+            .unwrap()
+            .with_language("en_US", "")
+            .is_err());
+        assert!(TextChunk::new("Title", "秘密").unwrap().to_chunk().is_err());
+
+        let chunk = Chunk::new(ChunkType::TEXT, b"no separator".to_vec());
+        assert!(TextChunk::try_from(&chunk).is_err());
+        let chunk = Chunk::new(ChunkType::ITXT, b"Title\x002\x00\x00\x00text".to_vec());
+        assert!(TextChunk::try_from(&chunk).is_err());
+        let chunk = Chunk::new(ChunkType::IDAT, b"Title\0text".to_vec());
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+}
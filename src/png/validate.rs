@@ -0,0 +1,563 @@
+use std::fmt::{self, Display};
+
+use itertools::Itertools;
+
+use crate::error::PngError;
+
+use super::{
+    chunk_type::ChunkType,
+    ihdr::{ColorType, Ihdr},
+    text::TextChunk,
+    Png,
+};
+
+/// How serious a `Finding` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Allowed by decoders, but not by the PNG spec.
+    Warning,
+    /// The image is corrupt and decoders may reject it.
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A violation of the PNG spec found by `Png::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// The first chunk isn't `IHDR`.
+    MissingIhdr,
+    InvalidIhdr(String),
+    MissingIdat,
+    /// `IDAT` chunks are separated by other chunks.
+    NonContiguousIdat,
+    MissingIend,
+    /// The `IEND` chunk is followed by more chunks.
+    IendNotLast,
+    /// `IEND` is supposed to be empty.
+    NonEmptyIend,
+    /// A chunk that may only appear once appears again.
+    Duplicate(ChunkType),
+    /// Indexed images need a palette.
+    MissingPlte,
+    /// Grayscale images can't have a palette.
+    UnexpectedPlte,
+    InvalidPlte(String),
+    /// A chunk must come before `chunk_type`.
+    MustPrecede(ChunkType, ChunkType),
+    /// A chunk must come after `chunk_type`.
+    MustFollow(ChunkType, ChunkType),
+    /// A chunk is not allowed with this colour type or combination of chunks.
+    NotAllowed(ChunkType, String),
+    /// A critical chunk this crate doesn't know, which decoders must reject.
+    UnknownCritical(ChunkType),
+    ReservedBitSet(ChunkType),
+    /// The stored CRC doesn't match, only possible in `ParseMode::Lenient`.
+    CrcMismatch(ChunkType),
+    InvalidText(String),
+    /// Bytes after the last chunk.
+    TrailingData(usize),
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::MissingIhdr => write!(f, "The first chunk must be 'IHDR'"),
+            Issue::InvalidIhdr(reason) => write!(f, "Invalid 'IHDR' chunk: {}", reason),
+            Issue::MissingIdat => write!(f, "Missing 'IDAT' chunk"),
+            Issue::NonContiguousIdat => write!(f, "'IDAT' chunks must be consecutive"),
+            Issue::MissingIend => write!(f, "Missing 'IEND' chunk"),
+            Issue::IendNotLast => write!(f, "'IEND' must be the last chunk"),
+            Issue::NonEmptyIend => write!(f, "'IEND' must be empty"),
+            Issue::Duplicate(chunk_type) => {
+                write!(f, "'{}' may only appear once", chunk_type)
+            }
+            Issue::MissingPlte => write!(f, "Indexed images require a 'PLTE' chunk"),
+            Issue::UnexpectedPlte => write!(f, "Grayscale images must not have a 'PLTE' chunk"),
+            Issue::InvalidPlte(reason) => write!(f, "Invalid 'PLTE' chunk: {}", reason),
+            Issue::MustPrecede(chunk_type, other) => {
+                write!(f, "'{}' must come before '{}'", chunk_type, other)
+            }
+            Issue::MustFollow(chunk_type, other) => {
+                write!(f, "'{}' must come after '{}'", chunk_type, other)
+            }
+            Issue::NotAllowed(chunk_type, reason) => {
+                write!(f, "'{}' is not allowed {}", chunk_type, reason)
+            }
+            Issue::UnknownCritical(chunk_type) => {
+                write!(f, "Unknown critical chunk '{}'", chunk_type)
+            }
+            Issue::ReservedBitSet(chunk_type) => {
+                write!(f, "The reserved bit of '{}' is set", chunk_type)
+            }
+            Issue::CrcMismatch(chunk_type) => {
+                write!(f, "The CRC of '{}' doesn't match its data", chunk_type)
+            }
+            Issue::InvalidText(reason) => write!(f, "{}", reason),
+            Issue::TrailingData(len) => {
+                write!(f, "{} bytes of trailing data after the last chunk", len)
+            }
+        }
+    }
+}
+
+/// A single problem found in a PNG, along with the index of the chunk it concerns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    severity: Severity,
+    index: Option<usize>,
+    issue: Issue,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "{} (chunk {}): {}", self.severity, index, self.issue),
+            None => write!(f, "{}: {}", self.severity, self.issue),
+        }
+    }
+}
+
+impl Finding {
+    fn new(severity: Severity, index: Option<usize>, issue: Issue) -> Finding {
+        Self {
+            severity,
+            index,
+            issue,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Index of the chunk this finding concerns, if any.
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub fn issue(&self) -> &Issue {
+        &self.issue
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+/// Where an ancillary chunk must be placed, and whether it may appear more than once. \
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.Summary-of-standard-chunks
+struct Rule {
+    chunk_type: [u8; 4],
+    before_plte: bool,
+    after_plte: bool,
+    before_idat: bool,
+    multiple: bool,
+}
+
+const fn rule(
+    chunk_type: &[u8; 4],
+    before_plte: bool,
+    after_plte: bool,
+    before_idat: bool,
+    multiple: bool,
+) -> Rule {
+    Rule {
+        chunk_type: *chunk_type,
+        before_plte,
+        after_plte,
+        before_idat,
+        multiple,
+    }
+}
+
+const RULES: [Rule; 14] = [
+    rule(b"cHRM", true, false, true, false),
+    rule(b"gAMA", true, false, true, false),
+    rule(b"iCCP", true, false, true, false),
+    rule(b"sBIT", true, false, true, false),
+    rule(b"sRGB", true, false, true, false),
+    rule(b"bKGD", false, true, true, false),
+    rule(b"hIST", false, true, true, false),
+    rule(b"tRNS", false, true, true, false),
+    rule(b"pHYs", false, false, true, false),
+    rule(b"sPLT", false, false, true, true),
+    rule(b"tIME", false, false, false, false),
+    rule(b"tEXt", false, false, false, true),
+    rule(b"zTXt", false, false, false, true),
+    rule(b"iTXt", false, false, false, true),
+];
+
+impl Png {
+    /// Checks this PNG against the chunk ordering and multiplicity rules of the PNG spec,
+    /// returning every problem found. A PNG without findings of `Severity::Error` is valid.
+    pub fn validate(&self) -> Vec<Finding> {
+        let mut findings = vec![];
+        let mut report = |severity, index: Option<usize>, issue| {
+            findings.push(Finding::new(severity, index, issue))
+        };
+        let chunks = self.chunks();
+        let positions = |chunk_type: &ChunkType| {
+            chunks
+                .iter()
+                .positions(|chunk| chunk.chunk_type() == chunk_type)
+                .collect_vec()
+        };
+
+        // IHDR
+        let ihdr = match chunks.first() {
+            Some(chunk) if chunk.chunk_type() == &ChunkType::IHDR => match Ihdr::try_from(chunk) {
+                Ok(ihdr) => Some(ihdr),
+                Err(err) => {
+                    let reason = match err {
+                        PngError::InvalidIhdr(reason) => reason,
+                        err => err.to_string(),
+                    };
+                    report(Severity::Error, Some(0), Issue::InvalidIhdr(reason));
+                    None
+                }
+            },
+            _ => {
+                report(Severity::Error, None, Issue::MissingIhdr);
+                None
+            }
+        };
+
+        // IDAT
+        let idats = positions(&ChunkType::IDAT);
+        let first_idat = idats.first().copied();
+        match (idats.first(), idats.last()) {
+            (Some(first), Some(last)) if last - first + 1 != idats.len() => {
+                let gap = idats.iter().tuple_windows().find(|(a, b)| *b - *a > 1);
+                report(
+                    Severity::Error,
+                    gap.map(|(_, b)| *b),
+                    Issue::NonContiguousIdat,
+                );
+            }
+            (None, _) => report(Severity::Error, None, Issue::MissingIdat),
+            _ => {}
+        }
+
+        // IEND
+        let iends = positions(&ChunkType::IEND);
+        match iends.first() {
+            None => report(Severity::Error, None, Issue::MissingIend),
+            Some(&idx) => {
+                if idx != chunks.len() - 1 {
+                    report(Severity::Error, Some(idx), Issue::IendNotLast);
+                }
+                if chunks[idx].length() != 0 {
+                    report(Severity::Warning, Some(idx), Issue::NonEmptyIend);
+                }
+            }
+        }
+
+        // PLTE
+        let plte = positions(&ChunkType::PLTE);
+        let first_plte = plte.first().copied();
+        if let Some(ihdr) = &ihdr {
+            match (ihdr.color_type(), first_plte) {
+                (ColorType::Indexed, None) => report(Severity::Error, None, Issue::MissingPlte),
+                (ColorType::Grayscale | ColorType::GrayscaleAlpha, Some(idx)) => {
+                    report(Severity::Error, Some(idx), Issue::UnexpectedPlte)
+                }
+                _ => {}
+            }
+        }
+        if let Some(idx) = first_plte {
+            let len = chunks[idx].length() as usize;
+            let max_entries = match &ihdr {
+                Some(ihdr) if ihdr.color_type() == ColorType::Indexed => 1 << ihdr.bit_depth(),
+                _ => 256,
+            };
+            if len == 0 || !len.is_multiple_of(3) || len / 3 > max_entries {
+                report(
+                    Severity::Error,
+                    Some(idx),
+                    Issue::InvalidPlte(format!(
+                        "{} bytes don't make up 1 to {} RGB entries",
+                        len, max_entries
+                    )),
+                );
+            }
+            if first_idat.is_some_and(|idat| idat < idx) {
+                report(
+                    Severity::Error,
+                    Some(idx),
+                    Issue::MustPrecede(ChunkType::PLTE, ChunkType::IDAT),
+                );
+            }
+        }
+
+        // Multiplicity of critical chunks
+        for (chunk_type, found) in [
+            (ChunkType::IHDR, positions(&ChunkType::IHDR)),
+            (ChunkType::PLTE, plte),
+            (ChunkType::IEND, iends),
+        ] {
+            for &idx in found.iter().skip(1) {
+                report(Severity::Error, Some(idx), Issue::Duplicate(chunk_type));
+            }
+        }
+
+        // Every other chunk
+        let mut seen = vec![];
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let chunk_type = *chunk.chunk_type();
+            if !chunk.has_valid_crc() {
+                report(Severity::Error, Some(idx), Issue::CrcMismatch(chunk_type));
+            }
+            if !chunk_type.is_reserved_bit_valid() {
+                report(
+                    Severity::Error,
+                    Some(idx),
+                    Issue::ReservedBitSet(chunk_type),
+                );
+            }
+            if [
+                ChunkType::IHDR,
+                ChunkType::IDAT,
+                ChunkType::IEND,
+                ChunkType::PLTE,
+            ]
+            .contains(&chunk_type)
+            {
+                continue;
+            }
+            if chunk_type.is_critical() {
+                report(
+                    Severity::Error,
+                    Some(idx),
+                    Issue::UnknownCritical(chunk_type),
+                );
+                continue;
+            }
+
+            let Some(rule) = RULES
+                .iter()
+                .find(|rule| rule.chunk_type == chunk_type.bytes())
+            else {
+                continue;
+            };
+            if !rule.multiple && seen.contains(&chunk_type) {
+                report(Severity::Warning, Some(idx), Issue::Duplicate(chunk_type));
+            }
+            seen.push(chunk_type);
+
+            if rule.before_plte && first_plte.is_some_and(|plte| plte < idx) {
+                report(
+                    Severity::Warning,
+                    Some(idx),
+                    Issue::MustPrecede(chunk_type, ChunkType::PLTE),
+                );
+            }
+            if rule.after_plte && first_plte.is_some_and(|plte| plte > idx) {
+                report(
+                    Severity::Warning,
+                    Some(idx),
+                    Issue::MustFollow(chunk_type, ChunkType::PLTE),
+                );
+            }
+            if rule.before_idat && first_idat.is_some_and(|idat| idat < idx) {
+                report(
+                    Severity::Warning,
+                    Some(idx),
+                    Issue::MustPrecede(chunk_type, ChunkType::IDAT),
+                );
+            }
+
+            match &chunk_type.bytes() {
+                b"hIST" if first_plte.is_none() => report(
+                    Severity::Warning,
+                    Some(idx),
+                    Issue::NotAllowed(chunk_type, "without a 'PLTE' chunk".into()),
+                ),
+                b"tRNS"
+                    if ihdr.as_ref().is_some_and(|ihdr| {
+                        matches!(
+                            ihdr.color_type(),
+                            ColorType::GrayscaleAlpha | ColorType::Rgba
+                        )
+                    }) =>
+                {
+                    report(
+                        Severity::Warning,
+                        Some(idx),
+                        Issue::NotAllowed(chunk_type, "in images with an alpha channel".into()),
+                    )
+                }
+                b"iCCP" | b"sRGB" => {
+                    if let Some(other) = seen.iter().find(|seen| {
+                        *seen != &chunk_type && [*b"iCCP", *b"sRGB"].contains(&seen.bytes())
+                    }) {
+                        report(
+                            Severity::Warning,
+                            Some(idx),
+                            Issue::NotAllowed(chunk_type, format!("together with '{}'", other)),
+                        )
+                    }
+                }
+                _ => {}
+            }
+
+            if TextChunk::is_text_chunk(&chunk_type) {
+                if let Err(err) = TextChunk::try_from(chunk) {
+                    report(
+                        Severity::Warning,
+                        Some(idx),
+                        Issue::InvalidText(err.to_string()),
+                    );
+                }
+            }
+        }
+
+        if !self.trailing_data().is_empty() {
+            report(
+                Severity::Warning,
+                None,
+                Issue::TrailingData(self.trailing_data().len()),
+            );
+        }
+
+        findings.sort_by_key(|finding| (finding.index.unwrap_or(usize::MAX), finding.severity));
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::chunk::Chunk;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn ihdr(color_type: ColorType) -> Chunk {
+        let ihdr = Ihdr::new(4, 4, 8, color_type, 0, 0, false).unwrap();
+        Chunk::new(ChunkType::IHDR, ihdr.as_bytes().to_vec())
+    }
+
+    fn issues(chunks: Vec<Chunk>) -> Vec<(Severity, Option<usize>, Issue)> {
+        Png::from_chunks(chunks)
+            .validate()
+            .into_iter()
+            .map(|finding| (finding.severity(), finding.index(), finding.issue().clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        assert_eq!(
+            issues(vec![
+                ihdr(ColorType::Indexed),
+                chunk("gAMA", &[0, 0, 177, 143]),
+                chunk("PLTE", &[0; 6]),
+                chunk("tRNS", &[0]),
+                chunk("IDAT", &[]),
+                chunk("IDAT", &[]),
+                chunk("tEXt", b"Comment\0hi"),
+                chunk("ruSt", &[]),
+                chunk("IEND", &[]),
+            ]),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_critical_structure() {
+        assert_eq!(
+            issues(vec![
+                chunk("IDAT", &[]),
+                chunk("IEND", &[1]),
+                chunk("IDAT", &[]),
+                chunk("RUSt", &[]),
+            ]),
+            vec![
+                (Severity::Warning, Some(1), Issue::NonEmptyIend),
+                (Severity::Error, Some(1), Issue::IendNotLast),
+                (Severity::Error, Some(2), Issue::NonContiguousIdat),
+                (
+                    Severity::Error,
+                    Some(3),
+                    Issue::UnknownCritical(ChunkType::from_str("RUSt").unwrap())
+                ),
+                (Severity::Error, None, Issue::MissingIhdr),
+            ]
+        );
+        assert_eq!(
+            issues(vec![ihdr(ColorType::Rgb), ihdr(ColorType::Rgb)]),
+            vec![
+                (Severity::Error, Some(1), Issue::Duplicate(ChunkType::IHDR)),
+                (Severity::Error, None, Issue::MissingIdat),
+                (Severity::Error, None, Issue::MissingIend),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_palette() {
+        assert_eq!(
+            issues(vec![
+                ihdr(ColorType::Indexed),
+                chunk("IDAT", &[]),
+                chunk("IEND", &[]),
+            ]),
+            vec![(Severity::Error, None, Issue::MissingPlte)]
+        );
+        let findings = issues(vec![
+            ihdr(ColorType::Grayscale),
+            chunk("IDAT", &[]),
+            chunk("PLTE", &[0; 4]),
+            chunk("IEND", &[]),
+        ]);
+        assert_eq!(
+            findings.iter().map(|(_, index, _)| *index).collect_vec(),
+            [Some(2); 3]
+        );
+        assert!(matches!(findings[0].2, Issue::UnexpectedPlte));
+        assert!(matches!(findings[1].2, Issue::InvalidPlte(_)));
+        assert_eq!(
+            findings[2].2,
+            Issue::MustPrecede(ChunkType::PLTE, ChunkType::IDAT)
+        );
+    }
+
+    #[test]
+    fn test_ancillary() {
+        let findings = issues(vec![
+            ihdr(ColorType::Rgba),
+            chunk("PLTE", &[0; 3]),
+            chunk("sRGB", &[0]),
+            chunk("iCCP", &[]),
+            chunk("tRNS", &[0; 6]),
+            chunk("IDAT", &[]),
+            chunk("pHYs", &[0; 9]),
+            chunk("tIME", &[0; 7]),
+            chunk("tIME", &[0; 7]),
+            chunk("tEXt", b"Comment"),
+            chunk("IEND", &[]),
+        ]);
+        assert!(findings
+            .iter()
+            .all(|(severity, _, _)| *severity == Severity::Warning));
+        assert_eq!(
+            findings
+                .iter()
+                .map(|(_, index, _)| index.unwrap())
+                .collect_vec(),
+            [2, 3, 3, 4, 6, 8, 9]
+        );
+        assert!(matches!(findings[2].2, Issue::NotAllowed(..)));
+        assert!(matches!(findings[6].2, Issue::InvalidText(_)));
+    }
+}